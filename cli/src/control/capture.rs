@@ -0,0 +1,266 @@
+use crate::control::fingerprint::COMMENT_KINDS;
+use crate::control::tag::ControlTag;
+use crate::parser::SupportedLanguage;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use tree_sitter::Node;
+
+/// The tag attribute that overrides the capture strategy for a single control comment.
+pub const CAPTURE_ATTRIBUTE: &str = "capture";
+
+const FUNCTION_KINDS: [&str; 13] = [
+    "arrow_function",
+    "closure_expression",
+    "constructor_declaration",
+    "func_literal",
+    "function",
+    "function_declaration",
+    "function_definition",
+    "function_expression",
+    "function_item",
+    "generator_function_declaration",
+    "lambda_expression",
+    "method_declaration",
+    "method_definition",
+];
+
+const CLASS_KINDS: [&str; 15] = [
+    "abstract_class_declaration",
+    "class",
+    "class_declaration",
+    "class_specifier",
+    "class_definition",
+    "enum_declaration",
+    "enum_item",
+    "impl_item",
+    "interface_declaration",
+    "record_declaration",
+    "struct_item",
+    "struct_specifier",
+    "trait_item",
+    "type_declaration",
+    "union_item",
+];
+
+/// Definitions that can be documented by a Python docstring.
+const DOCUMENTED_KINDS: [&str; 2] = ["class_definition", "function_definition"];
+
+/// Rust inner doc comments, which document the enclosing module or file.
+const INNER_DOC_DELIMITERS: [&str; 2] = ["//!", "/*!"];
+
+/// Which code a control comment attaches to.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Hash, Eq, PartialEq, Serialize)]
+pub enum Capture {
+    /// The node directly following the comment, the definition documented by a docstring, or the
+    /// module documented by an inner doc comment.
+    #[default]
+    Next,
+    /// The function or method declared after, or enclosing, the comment.
+    Function,
+    /// The class (or interface, enum, ...) declared after, or enclosing, the comment.
+    Class,
+    /// The whole file, for header comments.
+    File,
+}
+
+impl FromStr for Capture {
+    type Err = String;
+
+    fn from_str(capture: &str) -> Result<Capture, String> {
+        match capture {
+            "next" => Ok(Capture::Next),
+            "function" => Ok(Capture::Function),
+            "class" => Ok(Capture::Class),
+            "file" => Ok(Capture::File),
+            _ => Err(format!(
+                "Invalid capture: {} (expected next, function, class or file)",
+                capture
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Capture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Capture::Next => write!(f, "next"),
+            Capture::Function => write!(f, "function"),
+            Capture::Class => write!(f, "class"),
+            Capture::File => write!(f, "file"),
+        }
+    }
+}
+
+impl Capture {
+    /// Selects the node captured by the control comment `comment` of the source `source`, written
+    /// in `language`, or `None` when there is nothing to attach to (e.g. the comment is the last
+    /// statement before a closing `}`).
+    pub fn select<'tree>(
+        &self,
+        comment: Node<'tree>,
+        source: &str,
+        language: SupportedLanguage,
+    ) -> Option<Node<'tree>> {
+        match self {
+            Capture::Next => match documented(comment)
+                .or_else(|| inner_documented(comment, source, language))
+            {
+                Some(definition) => Some(definition),
+                None => next_code(comment)
+                    .filter(|node| node.is_named())
+                    .map(first_statement),
+            },
+            Capture::Function => select_declaration(comment, &FUNCTION_KINDS),
+            Capture::Class => select_declaration(comment, &CLASS_KINDS),
+            Capture::File => {
+                let mut root = comment;
+                while let Some(parent) = root.parent() {
+                    root = parent;
+                }
+                Some(root)
+            }
+        }
+    }
+}
+
+fn is_kind(node: Node, kinds: &[&str]) -> bool {
+    node.is_named() && kinds.contains(&node.kind())
+}
+
+/// The first sibling after `comment` that is not a comment itself, so that a control on any line of
+/// a group of line comments (such as a Go or Rust doc comment) attaches to the code after the group.
+/// Rust attributes are skipped as well, attaching to the item they annotate.
+fn next_code(comment: Node) -> Option<Node> {
+    let mut next_sibling = comment.next_sibling();
+    while let Some(sibling) = next_sibling.filter(|sibling| {
+        COMMENT_KINDS.contains(&sibling.kind()) || sibling.kind() == "attribute_item"
+    }) {
+        next_sibling = sibling.next_sibling();
+    }
+    next_sibling
+}
+
+/// Prefers a declaration directly following the comment (including one wrapped in an `export`
+/// or similar statement), falling back to the nearest enclosing declaration.
+fn select_declaration<'tree>(comment: Node<'tree>, kinds: &[&str]) -> Option<Node<'tree>> {
+    if let Some(next_sibling) = next_code(comment) {
+        let mut cursor = next_sibling.walk();
+        if is_kind(next_sibling, kinds)
+            || next_sibling
+                .named_children(&mut cursor)
+                .any(|child| is_kind(child, kinds))
+        {
+            return Some(next_sibling);
+        }
+    }
+
+    let mut ancestor = comment.parent();
+    while let Some(node) = ancestor {
+        if is_kind(node, kinds) {
+            return Some(with_decorators(node));
+        }
+        ancestor = node.parent();
+    }
+    None
+}
+
+/// A comment that opens an indented Python body precedes the `block` node rather than its first
+/// statement, so an unbraced block is narrowed to that statement.
+fn first_statement(node: Node) -> Node {
+    if !is_unbraced_block(node) {
+        return node;
+    }
+    let mut cursor = node.walk();
+    let first_statement = node
+        .named_children(&mut cursor)
+        .find(|child| !COMMENT_KINDS.contains(&child.kind()));
+    first_statement.unwrap_or(node)
+}
+
+fn is_unbraced_block(node: Node) -> bool {
+    node.kind() == "block" && node.child(0).is_some_and(|child| child.is_named())
+}
+
+/// The indented Python body opened by `comment`, whose statements follow the comment without being
+/// its siblings.
+pub fn opened_block(comment: Node) -> Option<Node> {
+    next_code(comment).filter(|node| is_unbraced_block(*node))
+}
+
+/// Widens a Python definition to include its decorators.
+fn with_decorators(definition: Node) -> Node {
+    definition
+        .parent()
+        .filter(|parent| parent.kind() == "decorated_definition")
+        .unwrap_or(definition)
+}
+
+/// Whether `node` is a Python docstring: a string that is the first statement of a class or
+/// function body.
+pub fn is_docstring(node: Node) -> bool {
+    documented(node).is_some()
+}
+
+/// The class or function (with its decorators) documented by the docstring `node`.
+fn documented(node: Node) -> Option<Node> {
+    if node.kind() != "string" {
+        return None;
+    }
+    let statement = node.parent().filter(|parent| parent.kind() == "expression_statement")?;
+    let block = statement.parent().filter(|parent| parent.kind() == "block")?;
+    let mut cursor = block.walk();
+    let first_statement = block
+        .named_children(&mut cursor)
+        .find(|child| !COMMENT_KINDS.contains(&child.kind()))?;
+    if first_statement != statement {
+        return None;
+    }
+    block
+        .parent()
+        .filter(|definition| DOCUMENTED_KINDS.contains(&definition.kind()))
+        .map(with_decorators)
+}
+
+/// The line comment `comment` followed by the line comments that continue it at the same column on
+/// the next lines (e.g. the rest of a Go or Rust doc comment), up to the next control tag. Block
+/// comments and docstrings are not grouped.
+pub fn line_comment_group<'tree>(comment: Node<'tree>, source: &str) -> Vec<Node<'tree>> {
+    let is_line_comment = |node: Node| {
+        let text = source[node.byte_range()].trim_end();
+        COMMENT_KINDS.contains(&node.kind()) && !text.contains('\n') && !text.starts_with("/*")
+    };
+    let mut group = vec![comment];
+    if !is_line_comment(comment) {
+        return group;
+    }
+    let mut last = comment;
+    while let Some(next) = last.next_sibling().filter(|next| {
+        is_line_comment(*next)
+            && next.start_position().row == last.start_position().row + 1
+            && next.start_position().column == comment.start_position().column
+            && ControlTag::parse(&source[next.byte_range()]).is_none()
+    }) {
+        group.push(next);
+        last = next;
+    }
+    group
+}
+
+/// The module (or, at the top level, the file) documented by the Rust inner doc comment `comment`.
+/// Other languages use the same delimiters for ordinary comments, e.g. Doxygen in C and C++.
+fn inner_documented<'tree>(
+    comment: Node<'tree>,
+    source: &str,
+    language: SupportedLanguage,
+) -> Option<Node<'tree>> {
+    let text = &source[comment.byte_range()];
+    if language != SupportedLanguage::Rust || !INNER_DOC_DELIMITERS.iter().any(|delimiter| text.starts_with(delimiter)) {
+        return None;
+    }
+    let parent = comment.parent()?;
+    match parent.parent() {
+        Some(module) if parent.kind() == "declaration_list" => Some(module),
+        _ => Some(parent),
+    }
+}
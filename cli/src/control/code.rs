@@ -1,12 +1,21 @@
-use crate::fs::{read_dir, read_file};
-use crate::parser::{parse, traverse_and_select};
-use regex::Regex;
+use crate::control::capture::{
+    is_docstring, line_comment_group, opened_block, Capture, CAPTURE_ATTRIBUTE,
+};
+use crate::control::digest::sha256;
+use crate::control::fingerprint::{Comparison, Fingerprint, COMMENT_KINDS};
+use crate::control::tag::{ControlId, ControlTag, Marker};
+use crate::error::Error;
+use crate::fs::{read_dir, read_file, relative_path, FileFilter};
+use crate::parser::{parse, traverse_and_select, LanguageMap, SupportedLanguage};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::fmt::Debug;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
 use tree_sitter::{Language, Node, TreeCursor};
 
-#[derive(Clone, Debug, Deserialize, Hash, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, Hash, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct Point {
     pub row: usize,
     pub column: usize,
@@ -24,11 +33,20 @@ impl From<tree_sitter::Point> for Point {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Hash, Eq, PartialEq, Serialize)]
+/// Code captured by a control comment.
+///
+/// Equality and hashing compare the SHA-256 digest of the captured content rather than the content
+/// itself, so entries whose content was omitted from the log still compare equal to a fresh scan.
+/// The fingerprint is left out of both; use `matches` to compare formatting-insensitively.
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct CommentedCode {
     path: PathBuf,
     comment: String,
-    content: String,
+    comment_digest: String,
+    tag: ControlTag,
+    content: Option<String>,
+    content_digest: String,
+    fingerprint: Option<Fingerprint>,
     start: Point,
     end: Point,
 }
@@ -37,19 +55,62 @@ impl CommentedCode {
     pub fn new(
         path: PathBuf,
         comment: String,
+        tag: ControlTag,
         content: String,
         start: Point,
         end: Point,
     ) -> CommentedCode {
         CommentedCode {
             path,
+            comment_digest: sha256(&comment),
             comment,
+            tag,
+            content_digest: sha256(&content),
+            content: Some(content),
+            fingerprint: None,
+            start,
+            end,
+        }
+    }
+
+    /// Restores an entry from a log that stores only the digest of omitted content.
+    pub(crate) fn from_digest(
+        path: PathBuf,
+        comment: String,
+        tag: ControlTag,
+        content: Option<String>,
+        content_digest: String,
+        start: Point,
+        end: Point,
+    ) -> CommentedCode {
+        CommentedCode {
+            path,
+            comment_digest: sha256(&comment),
+            comment,
+            tag,
             content,
+            content_digest,
+            fingerprint: None,
             start,
             end,
         }
     }
 
+    pub fn with_fingerprint(self, fingerprint: Fingerprint) -> CommentedCode {
+        CommentedCode {
+            fingerprint: Some(fingerprint),
+            ..self
+        }
+    }
+
+    /// Drops the captured source, keeping only its digest.
+    pub fn without_content(self) -> CommentedCode {
+        CommentedCode {
+            content: None,
+            ..self
+        }
+    }
+
     pub fn get_path(&self) -> PathBuf {
         self.path.clone()
     }
@@ -58,10 +119,32 @@ impl CommentedCode {
         self.comment.clone()
     }
 
-    pub fn get_content(&self) -> String {
+    pub fn get_comment_digest(&self) -> String {
+        self.comment_digest.clone()
+    }
+
+    pub fn get_tag(&self) -> ControlTag {
+        self.tag.clone()
+    }
+
+    pub fn get_controls(&self) -> Vec<ControlId> {
+        self.tag.get_controls()
+    }
+
+    /// The captured source, or `None` when it was omitted from the log.
+    pub fn get_content(&self) -> Option<String> {
         self.content.clone()
     }
 
+    pub fn get_content_digest(&self) -> String {
+        self.content_digest.clone()
+    }
+
+    /// The token-stream fingerprint, or `None` for entries read from logs older than version 3.
+    pub fn get_fingerprint(&self) -> Option<Fingerprint> {
+        self.fingerprint.clone()
+    }
+
     pub fn get_start(&self) -> Point {
         self.start.clone()
     }
@@ -69,101 +152,758 @@ impl CommentedCode {
     pub fn get_end(&self) -> Point {
         self.end.clone()
     }
+
+    /// The value `--diff` compares to decide whether content changed: the fingerprint under
+    /// normalized comparison, the content digest under exact comparison or without a fingerprint.
+    pub fn content_key(&self, comparison: Comparison) -> String {
+        match (comparison, &self.fingerprint) {
+            (Comparison::Normalized { ignore_comments }, Some(fingerprint)) => {
+                fingerprint.get(ignore_comments)
+            }
+            _ => self.content_digest.clone(),
+        }
+    }
+
+    fn key(&self) -> (&PathBuf, &str, &ControlTag, &str, &Point, &Point) {
+        (
+            &self.path,
+            &self.comment_digest,
+            &self.tag,
+            &self.content_digest,
+            &self.start,
+            &self.end,
+        )
+    }
+}
+
+impl PartialEq for CommentedCode {
+    fn eq(&self, other: &CommentedCode) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for CommentedCode {}
+
+impl Hash for CommentedCode {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+/// An invalid control comment, such as an unbalanced `control-start` / `control-end` region.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TagError {
+    path: PathBuf,
+    position: Point,
+    message: String,
+}
+
+impl TagError {
+    pub fn new(path: PathBuf, position: Point, message: String) -> TagError {
+        TagError {
+            path,
+            position,
+            message,
+        }
+    }
+
+    pub fn get_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    pub fn get_position(&self) -> Point {
+        self.position.clone()
+    }
+
+    pub fn get_message(&self) -> String {
+        self.message.clone()
+    }
 }
 
+impl fmt::Display for TagError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.path.display(),
+            self.position.row + 1,
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for TagError {}
+
+/// The result of scanning a directory for control comments.
+#[derive(Clone, Debug, Default)]
+pub struct ControlScan {
+    commented_code: Vec<CommentedCode>,
+    findings: Vec<TagError>,
+}
+
+impl ControlScan {
+    pub fn new(commented_code: Vec<CommentedCode>, findings: Vec<TagError>) -> ControlScan {
+        ControlScan {
+            commented_code,
+            findings,
+        }
+    }
+
+    pub fn get_commented_code(&self) -> Vec<CommentedCode> {
+        self.commented_code.clone()
+    }
+
+    /// Control comments that were skipped, because they had nothing to attach to or look like
+    /// control tags that could not be parsed.
+    pub fn get_findings(&self) -> Vec<TagError> {
+        self.findings.clone()
+    }
+
+    pub fn into_commented_code(self) -> Vec<CommentedCode> {
+        self.commented_code
+    }
+}
+
+fn describe(tag: &ControlTag) -> String {
+    let controls: Vec<String> = tag.get_controls().iter().map(|id| id.to_string()).collect();
+    format!("{} {}", tag.get_marker(), controls.join(" "))
+}
+
+/// Scans `path` for control comments.
+///
+/// Each file is parsed with the grammar of the language `languages` maps its extension to; files with unmapped
+/// extensions are skipped, and only the parser plugins of languages actually found are loaded.
+/// Files are read, parsed and scanned in parallel. Entries store their path relative to `path` and
+/// are ordered by path and position; when several files fail to scan, the error of the first one
+/// in path order is returned.
 pub fn get_control_commented_code(
     path: PathBuf,
-    language: Language,
-    ext: Vec<String>,
-) -> Vec<CommentedCode> {
-    let files = read_dir(path, &ext, read_file);
-    let re = Regex::new(r"control(?:\s\w+)+").unwrap();
+    languages: &LanguageMap,
+    filter: FileFilter,
+    capture: Capture,
+) -> Result<ControlScan, Error> {
+    let mut paths = read_dir(path.clone(), &filter, |path| Ok(vec![path]))?;
+    paths.sort();
+    let mut parsers: HashMap<String, Language> = HashMap::new();
+    let mut files = Vec::new();
+    for file_path in paths {
+        let extension = file_path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_string();
+        let language = match languages.get_language(&extension) {
+            Some(language) => language,
+            None => continue,
+        };
+        let parser = match parsers.get(&extension) {
+            Some(parser) => *parser,
+            None => {
+                let parser = language.language_for(&extension)?;
+                parsers.insert(extension, parser);
+                parser
+            }
+        };
+        files.push((file_path, language, parser));
+    }
+
+    let scans: Vec<Result<ControlScan, Error>> = files
+        .into_par_iter()
+        .map(|(file_path, language, parser)| scan_file(&path, file_path, language, parser, capture))
+        .collect();
+
+    let mut commented_code = Vec::new();
+    let mut findings = Vec::new();
+    for scan in scans {
+        let scan = scan?;
+        commented_code.extend(scan.commented_code);
+        findings.extend(scan.findings);
+    }
+    commented_code.sort_by(|a: &CommentedCode, b: &CommentedCode| {
+        (&a.path, &a.start, &a.end).cmp(&(&b.path, &b.start, &b.end))
+    });
+    Ok(ControlScan::new(commented_code, findings))
+}
+
+fn scan_file(
+    root: &Path,
+    path: PathBuf,
+    language: SupportedLanguage,
+    parser: Language,
+    capture: Capture,
+) -> Result<ControlScan, Error> {
     let mut commented_code = Vec::new();
-    for file in files {
-        let tree = parse(&file.get_contents(), language);
+    let mut findings = Vec::new();
+    for file in read_file(path)? {
+        let log_path = relative_path(root, &file.get_path());
+        let contents = file.get_contents();
+        let tree = parse(&contents, parser)?;
         let nodes = traverse_and_select(tree.root_node(), |cursor: TreeCursor| -> Option<Node> {
-            if cursor.node().kind() == "comment"
-                || cursor.node().kind() == "block_comment"
-                || cursor.node().kind() == "line_comment"
-            {
+            if COMMENT_KINDS.contains(&cursor.node().kind()) || is_docstring(cursor.node()) {
                 return Option::from(cursor.node());
             }
-            return None;
+            None
         });
 
+        let mut open_regions: Vec<(Node, ControlTag)> = Vec::new();
         for node in nodes {
-            let contents = file.get_contents();
-            let comment = node.utf8_text(contents.as_bytes()).unwrap();
-            let next_sibling = node.next_sibling().unwrap();
-            if re.is_match(comment) {
-                commented_code.push(CommentedCode::new(
-                    file.get_path(),
-                    comment.to_string(),
-                    next_sibling
-                        .utf8_text(file.get_contents().as_bytes())
-                        .unwrap()
-                        .to_string(),
-                    Point::from(node.next_sibling().unwrap().start_position()),
-                    Point::from(node.next_sibling().unwrap().end_position()),
-                ));
+            let comment = &contents[node.byte_range()];
+            let error = |node: Node, message: String| {
+                TagError::new(file.get_path(), Point::from(node.start_position()), message)
+            };
+            let group = line_comment_group(node, &contents);
+            let tag = if is_docstring(node) {
+                ControlTag::parse_docstring(comment)
+            } else {
+                let comments: Vec<&str> =
+                    group.iter().map(|node| &contents[node.byte_range()]).collect();
+                ControlTag::parse_group(&comments)
+            };
+            let tag = match tag {
+                Some(tag) => tag,
+                None => {
+                    if let Some(message) = ControlTag::diagnose(comment) {
+                        findings.push(error(node, message));
+                    }
+                    continue;
+                }
+            };
+
+            if tag.get_marker() != Marker::Control {
+                if let Some(capture) = tag.get_attribute(CAPTURE_ATTRIBUTE) {
+                    let message = format!(
+                        "{}={} on {} is ignored: a region captures the code up to its control-end",
+                        CAPTURE_ATTRIBUTE,
+                        capture,
+                        describe(&tag)
+                    );
+                    findings.push(error(node, message));
+                }
+            }
+
+            match tag.get_marker() {
+                Marker::Control => {
+                    let capture = match tag.get_attribute(CAPTURE_ATTRIBUTE) {
+                        Some(capture) => capture.parse().map_err(|message| error(node, message))?,
+                        None => capture,
+                    };
+                    let captured = match capture.select(node, &contents, language) {
+                        Some(captured) => captured,
+                        None => {
+                            let message = match capture {
+                                Capture::Next => {
+                                    format!("{} has nothing to attach to", describe(&tag))
+                                }
+                                _ => format!("{} has no {} to attach to", describe(&tag), capture),
+                            };
+                            findings.push(error(node, message));
+                            continue;
+                        }
+                    };
+                    let last_comment = group[group.len() - 1];
+                    commented_code.push(
+                        CommentedCode::new(
+                            log_path.clone(),
+                            contents[node.start_byte()..last_comment.end_byte()].to_string(),
+                            tag,
+                            contents[captured.byte_range()].to_string(),
+                            Point::from(captured.start_position()),
+                            Point::from(captured.end_position()),
+                        )
+                        .with_fingerprint(Fingerprint::new(captured, captured, &contents)),
+                    );
+                }
+                Marker::Start => open_regions.push((node, tag)),
+                Marker::End => {
+                    let (start_node, start_tag) = match open_regions.pop() {
+                        Some((start_node, start_tag))
+                            if start_tag.get_controls() == tag.get_controls() =>
+                        {
+                            (start_node, start_tag)
+                        }
+                        Some((start_node, start_tag)) => {
+                            let overlapped = open_regions
+                                .iter()
+                                .any(|(_, open)| open.get_controls() == tag.get_controls());
+                            let message = if overlapped {
+                                format!(
+                                    "{} overlaps {} opened at line {}",
+                                    describe(&tag),
+                                    describe(&start_tag),
+                                    start_node.start_position().row + 1
+                                )
+                            } else {
+                                format!(
+                                    "{} does not match {} opened at line {}",
+                                    describe(&tag),
+                                    describe(&start_tag),
+                                    start_node.start_position().row + 1
+                                )
+                            };
+                            return Err(error(node, message).into());
+                        }
+                        None => {
+                            return Err(error(
+                                node,
+                                format!("{} has no matching control-start", describe(&tag)),
+                            )
+                            .into());
+                        }
+                    };
+
+                    let opened = opened_block(start_node);
+                    if opened.or_else(|| start_node.parent()) != node.parent() {
+                        return Err(error(
+                            node,
+                            format!(
+                                "{} is not in the same block as {} at line {}",
+                                describe(&tag),
+                                describe(&start_tag),
+                                start_node.start_position().row + 1
+                            ),
+                        )
+                        .into());
+                    }
+                    let first = match opened {
+                        Some(block) => block.named_child(0),
+                        None => start_node.next_named_sibling(),
+                    }
+                    .filter(|first| *first != node);
+                    let last = node.prev_named_sibling().filter(|last| *last != start_node);
+                    let (first, last) = match (first, last) {
+                        (Some(first), Some(last)) => (first, last),
+                        _ => {
+                            return Err(error(
+                                start_node,
+                                format!("{} encloses no code", describe(&start_tag)),
+                            )
+                            .into());
+                        }
+                    };
+
+                    commented_code.push(
+                        CommentedCode::new(
+                            log_path.clone(),
+                            contents[start_node.byte_range()].to_string(),
+                            start_tag,
+                            contents[first.start_byte()..last.end_byte()].to_string(),
+                            Point::from(first.start_position()),
+                            Point::from(last.end_position()),
+                        )
+                        .with_fingerprint(Fingerprint::new(first, last, &contents)),
+                    );
+                }
             }
         }
-    }
-    return commented_code;
-}
 
-pub fn get_common_values<T: Eq + Clone + Debug>(v1: &Vec<T>, v2: &Vec<T>) -> Vec<T> {
-    let mut common_values = Vec::new();
-    for value in v1 {
-        if v2.contains(&value) {
-            common_values.push(value.clone());
+        if let Some((start_node, start_tag)) = open_regions.pop() {
+            return Err(TagError::new(
+                file.get_path(),
+                Point::from(start_node.start_position()),
+                format!("{} has no matching control-end", describe(&start_tag)),
+            )
+            .into());
         }
     }
-    return common_values;
+    Ok(ControlScan::new(commented_code, findings))
+}
+
+/// Returns the values of `v1` that also occur in `v2`, in the order of `v1`.
+#[deprecated(note = "no longer used by `diff`, which pairs entries by key")]
+pub fn get_common_values<T: Eq + Hash + Clone>(v1: &[T], v2: &[T]) -> Vec<T> {
+    let v2: HashSet<&T> = v2.iter().collect();
+    v1.iter().filter(|value| v2.contains(value)).cloned().collect()
 }
 
 #[test]
 fn check_get_control_commented_code() {
-    use crate::parser::SupportedLanguage;
     let workspace_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     let path = workspace_dir.join("tests/resources/js");
-    let ext = vec!["js".to_string(), "jsx".to_string()];
-    let language = SupportedLanguage::JavaScript.language();
+    let filter = FileFilter::new(vec!["js".to_string(), "jsx".to_string()]);
     let commented_code =
-        get_control_commented_code(path, language, ext);
+        get_control_commented_code(path, &LanguageMap::default(), filter, Capture::Next)
+            .unwrap()
+            .into_commented_code();
     assert_eq!(commented_code.len(), 4);
 
-    assert!(commented_code[0].get_path().to_str().unwrap().contains("tests/resources/js/subdirectory/component.jsx"));
-    assert_eq!(commented_code[0].get_comment(), "/** control SUB-1 **/");
+    assert_eq!(commented_code[0].get_path(), PathBuf::from("index.js"));
+    assert_eq!(
+        commented_code[0].get_comment(),
+        "/*\n * control D-3RP\n*/"
+    );
+    assert_eq!(commented_code[0].get_controls(), vec!["D-3RP".parse().unwrap()]);
+    assert_eq!(
+        commented_code[0].get_content().unwrap(),
+        "function derp() {\n  console.log('derp');\n}"
+    );
+
+    assert_eq!(commented_code[1].get_path(), PathBuf::from("index.js"));
     assert_eq!(
-        commented_code[0].get_content(),
+        commented_code[1].get_comment(),
+        "/* control HE-110 JS-1 */"
+    );
+    assert_eq!(
+        commented_code[1].get_controls(),
+        vec!["HE-110".parse::<ControlId>().unwrap(), "JS-1".parse().unwrap()]
+    );
+    assert_eq!(
+        commented_code[1].get_content().unwrap(),
+        "console.log('Hello world!');"
+    );
+
+    assert_eq!(commented_code[2].get_path(), PathBuf::from("subdirectory/component.jsx"));
+    assert_eq!(commented_code[2].get_comment(), "/** control SUB-1 **/");
+    assert_eq!(
+        commented_code[2].get_content().unwrap(),
         "return <div>Component</div>;"
     );
 
-    assert!(commented_code[1].get_path().to_str().unwrap().contains("tests/resources/js/subdirectory/submodule.js"));
-    assert_eq!(commented_code[1].get_comment(), "// control SUB-1");
+    assert_eq!(commented_code[3].get_path(), PathBuf::from("subdirectory/submodule.js"));
+    assert_eq!(commented_code[3].get_comment(), "// control SUB-1");
     assert_eq!(
-        commented_code[1].get_content(),
+        commented_code[3].get_content().unwrap(),
         "const submodule = () => {\n  return 'submodule';\n}"
     );
+}
+
+#[test]
+fn check_get_control_commented_code_regions() {
+    use assert_fs::prelude::*;
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("region.js")
+        .write_str(
+            "function main() {
+  const a = 1;
+  // control-start AC-3
+  const b = 2;
+  const c = 3;
+  // control HE-110
+  console.log(a + b + c);
+  // control-end AC-3
+}",
+        )
+        .unwrap();
+    let filter = FileFilter::new(vec!["js".to_string()]);
+    let commented_code =
+        get_control_commented_code(dir.path().to_path_buf(), &LanguageMap::default(), filter, Capture::Next)
+            .unwrap()
+            .into_commented_code();
+    assert_eq!(commented_code.len(), 2);
 
-    assert!(commented_code[2].get_path().to_str().unwrap().contains("tests/resources/js/index.js"));
+    assert_eq!(commented_code[0].get_comment(), "// control-start AC-3");
+    assert_eq!(commented_code[0].get_controls(), vec!["AC-3".parse().unwrap()]);
     assert_eq!(
-        commented_code[2].get_comment(),
-        "/*\n * control D-3RP\n*/"
+        commented_code[0].get_content().unwrap(),
+        "const b = 2;\n  const c = 3;\n  // control HE-110\n  console.log(a + b + c);"
     );
+    assert_eq!(commented_code[0].get_start(), Point::new(3, 2));
+    assert_eq!(commented_code[0].get_end(), Point::new(6, 25));
+
+    assert_eq!(commented_code[1].get_comment(), "// control HE-110");
+    assert_eq!(commented_code[1].get_content().unwrap(), "console.log(a + b + c);");
+}
+
+#[test]
+fn check_get_control_commented_code_python_regions() {
+    let commented_code = scan_fixture("regions", &["py"]);
+    assert_eq!(commented_code.len(), 1);
+    assert_eq!(commented_code[0].get_comment(), "# control-start AC-6");
     assert_eq!(
-        commented_code[2].get_content(),
-        "function derp() {\n  console.log('derp');\n}"
+        commented_code[0].get_content().unwrap(),
+        "source.withdraw(amount)\n    target.deposit(amount)"
     );
+    assert_eq!(commented_code[0].get_start(), Point::new(2, 4));
+    assert_eq!(commented_code[0].get_end(), Point::new(3, 26));
+}
 
-    assert!(commented_code[3].get_path().to_str().unwrap().contains("tests/resources/js/index.js"));
+#[test]
+fn check_get_control_commented_code_go_regions() {
+    let commented_code = scan_fixture("regions", &["go"]);
+    assert_eq!(commented_code.len(), 1);
     assert_eq!(
-        commented_code[3].get_comment(),
-        "/* control HE-110 JS-1 */"
+        commented_code[0].get_content().unwrap(),
+        "source.Withdraw(amount)\n\ttarget.Deposit(amount)"
+    );
+    assert_eq!(commented_code[0].get_start(), Point::new(4, 1));
+    assert_eq!(commented_code[0].get_end(), Point::new(5, 23));
+}
+
+#[test]
+fn check_get_control_commented_code_region_capture() {
+    use assert_fs::prelude::*;
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("region.js")
+        .write_str("// control-start AC-3 capture=function\nconst a = 1;\n// control-end AC-3\n")
+        .unwrap();
+    let scan = get_control_commented_code(
+        dir.path().to_path_buf(),
+        &LanguageMap::default(),
+        FileFilter::new(vec!["js".to_string()]),
+        Capture::Next,
+    )
+    .unwrap();
+
+    assert_eq!(scan.get_commented_code()[0].get_content().unwrap(), "const a = 1;");
+    let findings: Vec<String> = scan.get_findings().iter().map(|f| f.to_string()).collect();
+    assert_eq!(findings.len(), 1);
+    assert!(findings[0].ends_with(
+        "region.js:1: capture=function on control-start AC-3 is ignored: a region captures the code up to its control-end"
+    ));
+}
+
+#[test]
+fn check_get_control_commented_code_region_errors() {
+    use assert_fs::prelude::*;
+    let cases = [
+        ("// control-start AC-3\nconst a = 1;\n", "control-start AC-3 has no matching control-end"),
+        ("const a = 1;\n// control-end AC-3\n", "control-end AC-3 has no matching control-start"),
+        (
+            "// control-start AC-3\n// control-start AC-4\nconst a = 1;\n// control-end AC-3\n// control-end AC-4\n",
+            "control-end AC-3 overlaps control-start AC-4 opened at line 2",
+        ),
+        (
+            "// control-start AC-3\nfunction f() {\n  // control-end AC-3\n}\n",
+            "control-end AC-3 is not in the same block as control-start AC-3 at line 1",
+        ),
+    ];
+    for (source, message) in cases {
+        let dir = assert_fs::TempDir::new().unwrap();
+        dir.child("region.js").write_str(source).unwrap();
+        let error = get_control_commented_code(
+            dir.path().to_path_buf(),
+            &LanguageMap::default(),
+            FileFilter::new(vec!["js".to_string()]),
+            Capture::Next,
+        )
+        .unwrap_err();
+        match error {
+            Error::Tag(error) => assert_eq!(error.get_message(), message),
+            error => panic!("unexpected error: {}", error),
+        }
+    }
+}
+
+#[test]
+fn check_get_control_commented_code_capture() {
+    use assert_fs::prelude::*;
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("Capture.java")
+        .write_str(
+            "// control FILE-1 capture=file
+package example;
+
+class Capture {
+    // control CLS-1 capture=class
+    private int count;
+
+    // control FN-1
+    public void increment() {
+        // control FN-2 capture=function
+        count++;
+    }
+}",
+        )
+        .unwrap();
+    let commented_code = get_control_commented_code(
+        dir.path().to_path_buf(),
+        &LanguageMap::default(),
+        FileFilter::new(vec!["java".to_string()]),
+        Capture::Function,
+    )
+    .unwrap()
+    .into_commented_code();
+    assert_eq!(commented_code.len(), 4);
+
+    assert_eq!(commented_code[0].get_start(), Point::new(0, 0));
+    assert!(commented_code[0].get_content().unwrap().starts_with("// control FILE-1 capture=file\npackage example;"));
+    assert!(commented_code[1].get_content().unwrap().starts_with("class Capture {"));
+    assert!(commented_code[2].get_content().unwrap().starts_with("public void increment() {"));
+    assert_eq!(commented_code[2].get_content(), commented_code[3].get_content());
+}
+
+#[test]
+fn check_get_control_commented_code_orphans() {
+    use assert_fs::prelude::*;
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("orphan.js")
+        .write_str(
+            "function main() {
+  console.log('main');
+  // control AC-1
+}
+// control AC-2
+const a = 1;
+// control AC-3 capture=class
+// control AC-4
+",
+        )
+        .unwrap();
+    let scan = get_control_commented_code(
+        dir.path().to_path_buf(),
+        &LanguageMap::default(),
+        FileFilter::new(vec!["js".to_string()]),
+        Capture::Next,
+    )
+    .unwrap();
+
+    let commented_code = scan.get_commented_code();
+    assert_eq!(commented_code.len(), 1);
+    assert_eq!(commented_code[0].get_content().unwrap(), "const a = 1;");
+
+    let findings: Vec<String> = scan.get_findings().iter().map(|f| f.to_string()).collect();
+    assert_eq!(findings.len(), 3);
+    assert!(findings[0].ends_with("orphan.js:3: control AC-1 has nothing to attach to"));
+    assert!(findings[1].ends_with("orphan.js:7: control AC-3 has no class to attach to"));
+    assert!(findings[2].ends_with("orphan.js:8: control AC-4 has nothing to attach to"));
+}
+
+/// Scans the fixture directory `tests/resources/<dir>` for files with the given extensions.
+#[cfg(test)]
+fn scan_fixture(dir: &str, extensions: &[&str]) -> Vec<CommentedCode> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/resources").join(dir);
+    let extensions = extensions.iter().map(|extension| extension.to_string()).collect();
+    get_control_commented_code(path, &LanguageMap::default(), FileFilter::new(extensions), Capture::Next)
+        .unwrap()
+        .into_commented_code()
+}
+
+#[test]
+fn check_get_control_commented_code_typescript() {
+    let commented_code = scan_fixture("ts", &["ts", "tsx"]);
+    let contents: Vec<(PathBuf, String)> = commented_code
+        .iter()
+        .map(|code| (code.get_path(), code.get_content().unwrap()))
+        .collect();
+
+    assert!(contents.contains(&(
+        PathBuf::from("assertions.ts"),
+        "const length = (<string>input).length;".to_string()
+    )));
+    assert!(contents.contains(&(
+        PathBuf::from("assertions.ts"),
+        "const identity = <T>(value: T): T => value;".to_string()
+    )));
+    assert!(contents.contains(&(
+        PathBuf::from("subdirectory/component.tsx"),
+        "return <div>Component</div>;".to_string()
+    )));
+}
+
+#[test]
+fn check_get_control_commented_code_python() {
+    let commented_code = scan_fixture("py", &["py"]);
+    assert_eq!(commented_code.len(), 3);
+
+    assert_eq!(commented_code[0].get_controls(), vec!["AC-2".parse().unwrap()]);
+    assert_eq!(
+        commented_code[0].get_tag().get_justification(),
+        Some("Accounts are only created through the provisioning service.".to_string())
     );
+    assert!(commented_code[0].get_content().unwrap().starts_with("class Account:"));
+
+    assert_eq!(commented_code[1].get_controls(), vec!["AC-3".parse().unwrap()]);
+    assert_eq!(commented_code[1].get_tag().get_attribute("owner"), Some("iam".to_string()));
+    assert!(commented_code[1].get_content().unwrap().starts_with("@audited\n    def close(self):"));
+
+    assert_eq!(commented_code[2].get_controls(), vec!["AU-2".parse().unwrap()]);
     assert_eq!(
-        commented_code[3].get_content(),
-        "console.log('Hello world!');"
+        commented_code[2].get_content().unwrap(),
+        "log_transfer(source, target, amount)"
+    );
+}
+
+#[test]
+fn check_get_control_commented_code_go() {
+    let commented_code = scan_fixture("go", &["go"]);
+    assert_eq!(commented_code.len(), 3);
+
+    assert_eq!(commented_code[0].get_controls(), vec!["AC-2".parse().unwrap()]);
+    assert!(commented_code[0].get_content().unwrap().starts_with("type Account struct {"));
+    assert_eq!(
+        commented_code[0].get_tag().get_justification().unwrap(),
+        "Account is a user account, provisioned only through the identity provider."
     );
+
+    assert_eq!(commented_code[1].get_controls(), vec!["AC-3".parse().unwrap()]);
+    assert!(commented_code[1].get_content().unwrap().starts_with("func (a *Account) Close() error {"));
+    assert_eq!(commented_code[1].get_tag().get_justification(), None);
+
+    assert_eq!(commented_code[2].get_controls(), vec!["AU-2".parse().unwrap()]);
+    assert_eq!(commented_code[2].get_content().unwrap(), "audit(\"close\", a.Owner)");
+}
+
+#[test]
+fn check_get_control_commented_code_rust() {
+    let commented_code = scan_fixture("rs", &["rs"]);
+    assert_eq!(commented_code.len(), 4);
+
+    assert_eq!(commented_code[0].get_controls(), vec!["SC-23".parse().unwrap()]);
+    assert_eq!(commented_code[0].get_start(), Point::new(0, 0));
+    assert!(commented_code[0].get_content().unwrap().ends_with("pub fn revoke(_user: &str) {}\n}\n"));
+    assert_eq!(
+        commented_code[0].get_comment(),
+        "//! control SC-23\n//! Sessions are bound to a single client and expire after inactivity."
+    );
+    assert_eq!(
+        commented_code[0].get_tag().get_justification().unwrap(),
+        "Sessions are bound to a single client and expire after inactivity."
+    );
+
+    assert_eq!(commented_code[1].get_controls(), vec!["AC-12".parse().unwrap()]);
+    assert!(commented_code[1].get_content().unwrap().starts_with("pub struct Session {"));
+
+    assert_eq!(commented_code[2].get_controls(), vec!["AC-12(1)".parse().unwrap()]);
+    assert_eq!(commented_code[2].get_content().unwrap(), "self.last_seen.elapsed() > timeout");
+
+    assert_eq!(commented_code[3].get_controls(), vec!["AC-2(4)".parse().unwrap()]);
+    assert!(commented_code[3].get_content().unwrap().starts_with("mod revocation {"));
+}
+
+#[test]
+fn check_get_control_commented_code_c() {
+    let contents: Vec<(String, String)> = scan_fixture("c", &["c"])
+        .iter()
+        .map(|code| (code.get_controls()[0].to_string(), code.get_content().unwrap()))
+        .collect();
+    assert_eq!(contents.len(), 4);
+
+    assert_eq!(contents[0].0, "SI-17");
+    assert_eq!(
+        contents[0].1,
+        "#ifdef WATCHDOG_ENABLED\nstatic void reset_watchdog(void) {\n    fputs(\"watchdog reset\\n\", stderr);\n}\n#endif"
+    );
+    assert_eq!(contents[1].0, "SI-7");
+    assert!(contents[1].1.starts_with("int verify_image("));
+    assert_eq!(contents[2], ("AU-9".to_string(), "log_verification(image, length);".to_string()));
+    assert_eq!(contents[3].0, "SC-28");
+    assert!(contents[3].1.starts_with("void wipe_key("));
+
+    let justifications: Vec<Option<String>> = scan_fixture("c", &["c"])
+        .iter()
+        .map(|code| code.get_tag().get_justification())
+        .collect();
+    assert_eq!(
+        justifications,
+        vec![
+            None,
+            Some("Firmware images are verified before they are booted.".to_string()),
+            None,
+            Some("Keys are wiped from memory as soon as they are no longer needed.".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn check_get_control_commented_code_cpp() {
+    let contents: Vec<(String, String)> = scan_fixture("cpp", &["cpp"])
+        .iter()
+        .map(|code| (code.get_controls()[0].to_string(), code.get_content().unwrap()))
+        .collect();
+    assert_eq!(contents.len(), 3);
+
+    assert_eq!(contents[0].0, "SC-13");
+    assert!(contents[0].1.starts_with("class Cipher {"));
+    assert_eq!(contents[1].0, "AU-12");
+    assert!(contents[1].1.starts_with("void report(const std::string &event) {"));
+    assert_eq!(contents[2], ("SC-8".to_string(), "transmit(event);".to_string()));
 }
@@ -0,0 +1,316 @@
+use crate::control::code::CommentedCode;
+use crate::control::fingerprint::Comparison;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+
+/// How a control changed between a baseline log and a fresh scan.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum DiffKind {
+    Unchanged,
+    /// The same code under the same controls, now at another location.
+    Moved,
+    /// Code under the same controls in the same file, with different content.
+    Modified,
+    Added,
+    Removed,
+}
+
+impl fmt::Display for DiffKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiffKind::Unchanged => write!(f, "unchanged"),
+            DiffKind::Moved => write!(f, "moved"),
+            DiffKind::Modified => write!(f, "modified"),
+            DiffKind::Added => write!(f, "added"),
+            DiffKind::Removed => write!(f, "removed"),
+        }
+    }
+}
+
+/// A baseline entry paired with the scanned entry it corresponds to. `old` is `None` for added
+/// controls and `new` is `None` for removed ones.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DiffEntry {
+    kind: DiffKind,
+    old: Option<CommentedCode>,
+    new: Option<CommentedCode>,
+}
+
+impl DiffEntry {
+    pub fn new(kind: DiffKind, old: Option<CommentedCode>, new: Option<CommentedCode>) -> DiffEntry {
+        DiffEntry { kind, old, new }
+    }
+
+    pub fn get_kind(&self) -> DiffKind {
+        self.kind
+    }
+
+    pub fn get_old(&self) -> Option<CommentedCode> {
+        self.old.clone()
+    }
+
+    pub fn get_new(&self) -> Option<CommentedCode> {
+        self.new.clone()
+    }
+
+    fn location(&self) -> (std::path::PathBuf, usize) {
+        let commented_code = self.new.as_ref().or(self.old.as_ref()).unwrap();
+        (commented_code.get_path(), commented_code.get_start().row)
+    }
+}
+
+/// Pairs baseline entries with scanned entries and classifies each pair.
+///
+/// Entries are matched in passes: first unchanged code (at the same position when the comparison is
+/// exact, anywhere in the same file otherwise), then the same controls and content (moved when only
+/// the location changed, modified when the control comment changed),
+/// then the same controls in the same file (nearest first). Whatever is left is removed or added.
+/// The result is ordered by location.
+///
+/// Each pass indexes the unpaired scanned entries by a hashed key and then by row, so a diff takes
+/// O(n log n) time in the number of entries. Content is compared by fingerprint only when every
+/// entry has one, i.e. neither side was read from a log older than version 3.
+pub fn diff(old: Vec<CommentedCode>, new: Vec<CommentedCode>, comparison: Comparison) -> Vec<DiffEntry> {
+    let content_comparison = if old.iter().chain(&new).all(|entry| entry.get_fingerprint().is_some()) {
+        comparison
+    } else {
+        Comparison::Exact
+    };
+    let content = |entry: &CommentedCode| entry.content_key(content_comparison);
+    let same_comment = |old: &CommentedCode, new: &CommentedCode| match comparison {
+        Comparison::Exact => old.get_comment_digest() == new.get_comment_digest(),
+        Comparison::Normalized { .. } => old.get_tag() == new.get_tag(),
+    };
+    let mut old: Vec<Option<CommentedCode>> = old.into_iter().map(Some).collect();
+    let mut new: Vec<Option<CommentedCode>> = new.into_iter().map(Some).collect();
+    let mut entries = Vec::new();
+
+    match comparison {
+        Comparison::Exact => pair(
+            &mut old,
+            &mut new,
+            &mut entries,
+            |entry| entry.clone(),
+            |_| 0,
+            |_, _| DiffKind::Unchanged,
+        ),
+        Comparison::Normalized { .. } => pair(
+            &mut old,
+            &mut new,
+            &mut entries,
+            |entry| (entry.get_path(), entry.get_tag(), content(entry)),
+            |_| 0,
+            |_, _| DiffKind::Unchanged,
+        ),
+    }
+    pair(
+        &mut old,
+        &mut new,
+        &mut entries,
+        |entry| (entry.get_controls(), content(entry)),
+        |_| 0,
+        |old, new| {
+            let moved = old.get_path() != new.get_path()
+                || (comparison == Comparison::Exact && old.get_start() != new.get_start());
+            if moved && same_comment(old, new) {
+                DiffKind::Moved
+            } else {
+                DiffKind::Modified
+            }
+        },
+    );
+    pair(
+        &mut old,
+        &mut new,
+        &mut entries,
+        |entry| (entry.get_controls(), entry.get_path()),
+        |entry| entry.get_start().row,
+        |_, _| DiffKind::Modified,
+    );
+
+    entries.extend(
+        old.into_iter()
+            .flatten()
+            .map(|old| DiffEntry::new(DiffKind::Removed, Some(old), None)),
+    );
+    entries.extend(
+        new.into_iter()
+            .flatten()
+            .map(|new| DiffEntry::new(DiffKind::Added, None, Some(new))),
+    );
+    entries.sort_by_cached_key(|entry| (entry.location(), entry.kind));
+    entries
+}
+
+/// Pairs every unpaired old entry with the unpaired new entry of the same `key` whose `row` is
+/// nearest, preferring earlier entries on ties, and classifies the pair with `kind`.
+fn pair<K, F, R, C>(
+    old: &mut [Option<CommentedCode>],
+    new: &mut [Option<CommentedCode>],
+    entries: &mut Vec<DiffEntry>,
+    key: F,
+    row: R,
+    kind: C,
+) where
+    K: Eq + Hash,
+    F: Fn(&CommentedCode) -> K,
+    R: Fn(&CommentedCode) -> usize,
+    C: Fn(&CommentedCode, &CommentedCode) -> DiffKind,
+{
+    let mut index: HashMap<K, BTreeMap<usize, VecDeque<usize>>> = HashMap::new();
+    for (position, new_entry) in new.iter().enumerate() {
+        if let Some(new_entry) = new_entry {
+            index
+                .entry(key(new_entry))
+                .or_default()
+                .entry(row(new_entry))
+                .or_default()
+                .push_back(position);
+        }
+    }
+
+    for old_slot in old.iter_mut() {
+        let old_entry = match old_slot {
+            Some(old_entry) => old_entry,
+            None => continue,
+        };
+        let rows = match index.get_mut(&key(old_entry)) {
+            Some(rows) => rows,
+            None => continue,
+        };
+        let old_row = row(old_entry);
+        let before = rows.range(..=old_row).next_back().map(|(row, _)| *row);
+        let after = rows.range(old_row..).next().map(|(row, _)| *row);
+        let nearest = match (before, after) {
+            (Some(before), Some(after)) if after - old_row < old_row - before => after,
+            (Some(before), _) => before,
+            (None, Some(after)) => after,
+            (None, None) => continue,
+        };
+        let positions = rows.get_mut(&nearest).unwrap();
+        let position = positions.pop_front().unwrap();
+        if positions.is_empty() {
+            rows.remove(&nearest);
+        }
+        let new_entry = new[position].take().unwrap();
+        let kind = kind(old_entry, &new_entry);
+        entries.push(DiffEntry::new(kind, old_slot.take(), Some(new_entry)));
+    }
+}
+
+#[test]
+fn check_diff() {
+    use crate::control::code::Point;
+    use crate::control::tag::ControlTag;
+    use std::path::PathBuf;
+
+    let entry = |path: &str, comment: &str, content: &str, row: usize| {
+        CommentedCode::new(
+            PathBuf::from(path),
+            comment.to_string(),
+            ControlTag::parse(comment).unwrap(),
+            content.to_string(),
+            Point::new(row, 0),
+            Point::new(row, content.len()),
+        )
+    };
+    let old = vec![
+        entry("a.js", "// control AC-1", "same();", 1),
+        entry("a.js", "// control AC-2", "moving();", 2),
+        entry("a.js", "// control AC-3", "before();", 3),
+        entry("a.js", "// control AC-4", "gone();", 4),
+        entry("a.js", "// control AC-6 owner=a", "tagged();", 6),
+        entry("a.js", "// control AC-7", "down();", 8),
+    ];
+    let new = vec![
+        entry("a.js", "// control AC-1", "same();", 1),
+        entry("b.js", "// control AC-2", "moving();", 7),
+        entry("a.js", "// control AC-3", "after();", 3),
+        entry("a.js", "// control AC-5", "fresh();", 5),
+        entry("a.js", "// control AC-6 owner=b", "tagged();", 6),
+        entry("a.js", "// control AC-7", "down();", 11),
+    ];
+
+    let moved = diff(old.clone(), new.clone(), Comparison::Exact);
+    assert_eq!(moved[5].get_kind(), DiffKind::Moved);
+    assert_eq!(moved[5].get_new().unwrap().get_start().row, 11);
+
+    let entries = diff(old, new, Comparison::Normalized { ignore_comments: false });
+    let kinds: Vec<(DiffKind, Option<PathBuf>)> = entries
+        .iter()
+        .map(|entry| (entry.get_kind(), entry.get_new().map(|new| new.get_path())))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (DiffKind::Unchanged, Some(PathBuf::from("a.js"))),
+            (DiffKind::Modified, Some(PathBuf::from("a.js"))),
+            (DiffKind::Removed, None),
+            (DiffKind::Added, Some(PathBuf::from("a.js"))),
+            (DiffKind::Modified, Some(PathBuf::from("a.js"))),
+            (DiffKind::Unchanged, Some(PathBuf::from("a.js"))),
+            (DiffKind::Moved, Some(PathBuf::from("b.js"))),
+        ]
+    );
+    assert_eq!(entries[2].get_old().unwrap().get_controls(), vec!["AC-4".parse().unwrap()]);
+}
+
+#[test]
+fn check_diff_large() {
+    use crate::control::code::Point;
+    use crate::control::tag::ControlTag;
+    use std::path::PathBuf;
+
+    let entry = |control: usize, content: &str| {
+        let comment = format!("// control AC-{}", control);
+        CommentedCode::new(
+            PathBuf::from(format!("src/{}.js", control % 100)),
+            comment.clone(),
+            ControlTag::parse(&comment).unwrap(),
+            format!("{}({});", content, control),
+            Point::new(control, 0),
+            Point::new(control, 10),
+        )
+    };
+    let old: Vec<CommentedCode> = (0..50_000).map(|control| entry(control, "call")).collect();
+    let new: Vec<CommentedCode> = (0..50_000)
+        .map(|control| entry(control, if control % 10 == 0 { "changed" } else { "call" }))
+        .collect();
+
+    let entries = diff(old, new, Comparison::Exact);
+    assert_eq!(entries.len(), 50_000);
+    let modified = entries
+        .iter()
+        .filter(|entry| entry.get_kind() == DiffKind::Modified)
+        .count();
+    assert_eq!(modified, 5_000);
+}
+
+#[test]
+fn check_diff_large_shared_key() {
+    use crate::control::code::Point;
+    use crate::control::tag::ControlTag;
+    use std::path::PathBuf;
+
+    // Every entry shares its controls and path, so the modified pass sees a single bucket.
+    let entry = |row: usize, content: &str| {
+        CommentedCode::new(
+            PathBuf::from("src/a.js"),
+            "// control AC-1".to_string(),
+            ControlTag::parse("// control AC-1").unwrap(),
+            format!("{}({});", content, row),
+            Point::new(row, 0),
+            Point::new(row, 10),
+        )
+    };
+    let old: Vec<CommentedCode> = (0..20_000).map(|row| entry(row, "call")).collect();
+    let new: Vec<CommentedCode> = (1..=20_000).map(|row| entry(row, "changed")).collect();
+
+    let entries = diff(old, new, Comparison::Exact);
+    assert_eq!(entries.len(), 20_000);
+    assert!(entries.iter().all(|entry| {
+        entry.get_kind() == DiffKind::Modified
+            && entry.get_old().unwrap().get_start().row + 1 == entry.get_new().unwrap().get_start().row
+    }));
+}
@@ -0,0 +1,14 @@
+use sha2::{Digest, Sha256};
+
+/// Returns the lowercase hex SHA-256 digest of `content`.
+pub fn sha256(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+#[test]
+fn check_sha256() {
+    assert_eq!(
+        sha256("abc"),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+}
@@ -0,0 +1,118 @@
+use crate::control::digest::sha256;
+use crate::control::tag::ControlTag;
+use serde::{Deserialize, Serialize};
+use tree_sitter::Node;
+
+/// Node kinds that tree-sitter grammars use for comments.
+pub const COMMENT_KINDS: [&str; 3] = ["block_comment", "comment", "line_comment"];
+
+/// Separates tokens before hashing, so that `a b` and `ab` fingerprint differently.
+const TOKEN_SEPARATOR: char = '\u{1f}';
+
+/// Digests of the token stream of captured code, insensitive to whitespace and formatting.
+#[derive(Clone, Debug, Deserialize, Hash, Eq, PartialEq, Serialize)]
+pub struct Fingerprint {
+    tokens: String,
+    code_tokens: String,
+}
+
+impl Fingerprint {
+    /// Fingerprints the sibling nodes `first` to `last` (inclusive) of the source `source`.
+    pub fn new(first: Node, last: Node, source: &str) -> Fingerprint {
+        let mut tokens = Vec::new();
+        let mut code_tokens = Vec::new();
+        let mut node = Some(first);
+        while let Some(current) = node {
+            collect_tokens(current, source, &mut tokens, &mut code_tokens);
+            if current == last {
+                break;
+            }
+            node = current.next_sibling();
+        }
+        Fingerprint {
+            tokens: digest(&tokens),
+            code_tokens: digest(&code_tokens),
+        }
+    }
+
+    /// The digest of all tokens, or of all tokens except non-control comments.
+    pub fn get(&self, ignore_comments: bool) -> String {
+        if ignore_comments {
+            self.code_tokens.clone()
+        } else {
+            self.tokens.clone()
+        }
+    }
+}
+
+/// How `--diff` decides whether controlled code changed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Comparison {
+    /// Compares the exact content and position of the code.
+    Exact,
+    /// Compares fingerprints, optionally ignoring non-control comments.
+    Normalized { ignore_comments: bool },
+}
+
+fn digest(tokens: &[String]) -> String {
+    sha256(&tokens.join(&TOKEN_SEPARATOR.to_string()))
+}
+
+/// Collects the leaf tokens of `node`. Source text between child nodes that is not whitespace
+/// (e.g. the characters of a string literal whose grammar has no node for them) is kept as is.
+fn collect_tokens(node: Node, source: &str, tokens: &mut Vec<String>, code_tokens: &mut Vec<String>) {
+    let text = &source[node.byte_range()];
+    if COMMENT_KINDS.contains(&node.kind()) {
+        let comment = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if ControlTag::parse(text).is_some() {
+            code_tokens.push(comment.clone());
+        }
+        tokens.push(comment);
+        return;
+    }
+    if node.child_count() == 0 {
+        tokens.push(text.to_string());
+        code_tokens.push(text.to_string());
+        return;
+    }
+
+    let mut gap_start = node.start_byte();
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        push_gap(&source[gap_start..child.start_byte()], tokens, code_tokens);
+        collect_tokens(child, source, tokens, code_tokens);
+        gap_start = child.end_byte();
+    }
+    push_gap(&source[gap_start..node.end_byte()], tokens, code_tokens);
+}
+
+fn push_gap(gap: &str, tokens: &mut Vec<String>, code_tokens: &mut Vec<String>) {
+    if !gap.trim().is_empty() {
+        tokens.push(gap.to_string());
+        code_tokens.push(gap.to_string());
+    }
+}
+
+#[test]
+fn check_fingerprint() {
+    use crate::parser::{parse, SupportedLanguage};
+    let language = "js".parse::<SupportedLanguage>().unwrap().language().unwrap();
+    let fingerprint = |source: &str| {
+        let tree = parse(source, language).unwrap();
+        let root = tree.root_node();
+        Fingerprint::new(root, root, source)
+    };
+
+    let original = fingerprint("function f(a) {\n  return a + 1; // add one\n}\n");
+    let reformatted = fingerprint("function f( a ){\n\treturn a+1;   //   add one\n}");
+    let recommented = fingerprint("function f(a) {\n  return a + 1; // increment\n}\n");
+    let changed = fingerprint("function f(a) {\n  return a + 2; // add one\n}\n");
+    let string = fingerprint("function f(a) {\n  return 'a  b'; // add one\n}\n");
+    let restrung = fingerprint("function f(a) {\n  return 'a b'; // add one\n}\n");
+
+    assert_eq!(original, reformatted);
+    assert_ne!(original.get(false), recommented.get(false));
+    assert_eq!(original.get(true), recommented.get(true));
+    assert_ne!(original.get(true), changed.get(true));
+    assert_ne!(string.get(true), restrung.get(true));
+}
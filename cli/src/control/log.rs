@@ -0,0 +1,219 @@
+use crate::control::code::{CommentedCode, Point};
+use crate::control::tag::{ControlTag, Marker};
+use crate::error::{Error, LogStage};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Identifies a versioned control log. Logs without it predate versioning (version 0).
+pub const MAGIC: [u8; 8] = *b"CTRLLOG\0";
+/// The format version written by this build.
+pub const FORMAT_VERSION: u32 = 3;
+
+/// Describes how a control log was generated.
+///
+/// The header deliberately carries no timestamp or host information, so that generating a log twice
+/// from the same sources yields the same bytes.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct LogHeader {
+    tool_version: String,
+    language: String,
+    extensions: Vec<String>,
+    capture: String,
+}
+
+impl LogHeader {
+    pub fn new(language: String, extensions: Vec<String>, capture: String) -> LogHeader {
+        LogHeader {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            language,
+            extensions,
+            capture,
+        }
+    }
+
+    fn legacy() -> LogHeader {
+        LogHeader {
+            tool_version: String::new(),
+            language: String::new(),
+            extensions: Vec::new(),
+            capture: String::new(),
+        }
+    }
+
+    /// The version of `control` that generated the log, empty for version 0 logs.
+    pub fn get_tool_version(&self) -> String {
+        self.tool_version.clone()
+    }
+
+    /// The languages scanned, comma-separated.
+    pub fn get_language(&self) -> String {
+        self.language.clone()
+    }
+
+    pub fn get_extensions(&self) -> Vec<String> {
+        self.extensions.clone()
+    }
+
+    pub fn get_capture(&self) -> String {
+        self.capture.clone()
+    }
+}
+
+/// A decoded `.control-log`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ControlLog {
+    format_version: u32,
+    header: LogHeader,
+    commented_code: Vec<CommentedCode>,
+}
+
+impl ControlLog {
+    pub fn new(header: LogHeader, commented_code: Vec<CommentedCode>) -> ControlLog {
+        ControlLog {
+            format_version: FORMAT_VERSION,
+            header,
+            commented_code,
+        }
+    }
+
+    pub fn get_format_version(&self) -> u32 {
+        self.format_version
+    }
+
+    pub fn get_header(&self) -> LogHeader {
+        self.header.clone()
+    }
+
+    pub fn get_commented_code(&self) -> Vec<CommentedCode> {
+        self.commented_code.clone()
+    }
+
+    pub fn into_commented_code(self) -> Vec<CommentedCode> {
+        self.commented_code
+    }
+
+    /// Encodes the log as `MAGIC`, the little-endian format version and the bincode body.
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        let body = bincode::serialize(&(&self.header, &self.commented_code))
+            .map_err(|err| Error::log_corrupt(LogStage::Body, err))?;
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 4 + body.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&body);
+        Ok(bytes)
+    }
+
+    /// Decodes a version 3 body.
+    pub fn decode_v3(body: &[u8]) -> Result<ControlLog, Error> {
+        let (header, commented_code): (LogHeader, Vec<CommentedCode>) =
+            bincode::deserialize(body).map_err(|err| Error::log_corrupt(LogStage::Body, err))?;
+        Ok(ControlLog {
+            format_version: 3,
+            header,
+            commented_code,
+        })
+    }
+
+    /// Decodes a version 2 body, whose entries carry digests but no fingerprint.
+    pub fn decode_v2(body: &[u8]) -> Result<ControlLog, Error> {
+        let (header, commented_code): (LogHeader, Vec<CommentedCodeV2>) =
+            bincode::deserialize(body).map_err(|err| Error::log_corrupt(LogStage::Body, err))?;
+        Ok(ControlLog {
+            format_version: 2,
+            header,
+            commented_code: commented_code.into_iter().map(CommentedCode::from).collect(),
+        })
+    }
+
+    /// Decodes a version 1 body, whose entries carry the captured content but no digests.
+    pub fn decode_v1(body: &[u8]) -> Result<ControlLog, Error> {
+        let (header, commented_code): (LogHeader, Vec<CommentedCodeV1>) =
+            bincode::deserialize(body).map_err(|err| Error::log_corrupt(LogStage::Body, err))?;
+        Ok(ControlLog {
+            format_version: 1,
+            header,
+            commented_code: commented_code.into_iter().map(CommentedCode::from).collect(),
+        })
+    }
+
+    /// Decodes an unversioned log, a bare bincode `Vec` of commented code without parsed tags.
+    pub fn decode_v0(bytes: &[u8]) -> Result<ControlLog, Error> {
+        let commented_code: Vec<LegacyCommentedCode> =
+            bincode::deserialize(bytes).map_err(|err| Error::log_corrupt(LogStage::Body, err))?;
+        Ok(ControlLog {
+            format_version: 0,
+            header: LogHeader::legacy(),
+            commented_code: commented_code.into_iter().map(CommentedCode::from).collect(),
+        })
+    }
+}
+
+/// The entry layout of version 2 logs.
+#[derive(Deserialize)]
+struct CommentedCodeV2 {
+    path: PathBuf,
+    comment: String,
+    _comment_digest: String,
+    tag: ControlTag,
+    content: Option<String>,
+    content_digest: String,
+    start: Point,
+    end: Point,
+}
+
+impl From<CommentedCodeV2> for CommentedCode {
+    fn from(v2: CommentedCodeV2) -> CommentedCode {
+        CommentedCode::from_digest(
+            v2.path,
+            v2.comment,
+            v2.tag,
+            v2.content,
+            v2.content_digest,
+            v2.start,
+            v2.end,
+        )
+    }
+}
+
+/// The entry layout of version 1 logs.
+#[derive(Deserialize)]
+struct CommentedCodeV1 {
+    path: PathBuf,
+    comment: String,
+    tag: ControlTag,
+    content: String,
+    start: Point,
+    end: Point,
+}
+
+impl From<CommentedCodeV1> for CommentedCode {
+    fn from(v1: CommentedCodeV1) -> CommentedCode {
+        CommentedCode::new(v1.path, v1.comment, v1.tag, v1.content, v1.start, v1.end)
+    }
+}
+
+/// The entry layout of version 0 logs.
+#[derive(Deserialize)]
+struct LegacyCommentedCode {
+    path: PathBuf,
+    comment: String,
+    content: String,
+    start: Point,
+    end: Point,
+}
+
+impl From<LegacyCommentedCode> for CommentedCode {
+    fn from(legacy: LegacyCommentedCode) -> CommentedCode {
+        let tag = ControlTag::parse(&legacy.comment)
+            .unwrap_or_else(|| ControlTag::new(Marker::Control, Vec::new(), BTreeMap::new(), None));
+        CommentedCode::new(
+            legacy.path,
+            legacy.comment,
+            tag,
+            legacy.content,
+            legacy.start,
+            legacy.end,
+        )
+    }
+}
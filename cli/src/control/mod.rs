@@ -1 +1,8 @@
+pub mod capture;
 pub mod code;
+pub mod diff;
+pub mod digest;
+pub mod fingerprint;
+pub mod log;
+pub mod report;
+pub mod tag;
@@ -0,0 +1,461 @@
+use crate::control::code::CommentedCode;
+use crate::control::diff::{DiffEntry, DiffKind};
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+const CSV_HEADER: [&str; 9] = [
+    "path",
+    "start_line",
+    "end_line",
+    "controls",
+    "attributes",
+    "justification",
+    "comment",
+    "comment_hash",
+    "content_hash",
+];
+const TABLE_HEADER: [&str; 4] = ["PATH", "LINES", "CONTROLS", "CONTENT HASH"];
+const TABLE_HASH_LENGTH: usize = 12;
+
+/// Output format of `control control log`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum LogFormat {
+    #[default]
+    Json,
+    Yaml,
+    Csv,
+    Table,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<LogFormat, String> {
+        match format {
+            "json" => Ok(LogFormat::Json),
+            "yaml" => Ok(LogFormat::Yaml),
+            "csv" => Ok(LogFormat::Csv),
+            "table" => Ok(LogFormat::Table),
+            _ => Err(format!(
+                "Invalid format: {} (expected json, yaml, csv or table)",
+                format
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogFormat::Json => write!(f, "json"),
+            LogFormat::Yaml => write!(f, "yaml"),
+            LogFormat::Csv => write!(f, "csv"),
+            LogFormat::Table => write!(f, "table"),
+        }
+    }
+}
+
+/// Output format of `control control code --diff`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DiffFormat {
+    #[default]
+    Text,
+    Json,
+    Markdown,
+}
+
+impl FromStr for DiffFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<DiffFormat, String> {
+        match format {
+            "text" => Ok(DiffFormat::Text),
+            "json" => Ok(DiffFormat::Json),
+            "markdown" => Ok(DiffFormat::Markdown),
+            _ => Err(format!(
+                "Invalid diff format: {} (expected text, json or markdown)",
+                format
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DiffFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiffFormat::Text => write!(f, "text"),
+            DiffFormat::Json => write!(f, "json"),
+            DiffFormat::Markdown => write!(f, "markdown"),
+        }
+    }
+}
+
+/// A control log entry in the documented output schema. Unlike `CommentedCode`, field names and
+/// meanings are stable across releases.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct LogRecord {
+    path: String,
+    start_line: usize,
+    end_line: usize,
+    controls: Vec<String>,
+    attributes: BTreeMap<String, String>,
+    justification: Option<String>,
+    comment: String,
+    comment_hash: String,
+    content_hash: String,
+}
+
+impl From<&CommentedCode> for LogRecord {
+    fn from(commented_code: &CommentedCode) -> LogRecord {
+        let tag = commented_code.get_tag();
+        LogRecord {
+            path: commented_code.get_path().to_string_lossy().to_string(),
+            start_line: commented_code.get_start().row + 1,
+            end_line: commented_code.get_end().row + 1,
+            controls: tag.get_controls().iter().map(|id| id.to_string()).collect(),
+            attributes: tag.get_attributes(),
+            justification: tag.get_justification(),
+            comment: commented_code.get_comment(),
+            comment_hash: commented_code.get_comment_digest(),
+            content_hash: commented_code.get_content_digest(),
+        }
+    }
+}
+
+impl LogRecord {
+    pub fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    pub fn get_content_hash(&self) -> String {
+        self.content_hash.clone()
+    }
+
+    fn lines(&self) -> String {
+        format!("{}-{}", self.start_line, self.end_line)
+    }
+
+    fn attributes(&self) -> String {
+        let attributes: Vec<String> = self
+            .attributes
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        attributes.join(";")
+    }
+}
+
+/// One side of a changed control in the documented diff schema.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct DiffLocation {
+    path: String,
+    start_line: usize,
+    end_line: usize,
+    content_hash: String,
+}
+
+impl From<&CommentedCode> for DiffLocation {
+    fn from(commented_code: &CommentedCode) -> DiffLocation {
+        DiffLocation {
+            path: commented_code.get_path().to_string_lossy().to_string(),
+            start_line: commented_code.get_start().row + 1,
+            end_line: commented_code.get_end().row + 1,
+            content_hash: commented_code.get_content_digest(),
+        }
+    }
+}
+
+impl fmt::Display for DiffLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}-{}", self.path, self.start_line, self.end_line)
+    }
+}
+
+/// A changed control in the documented diff schema. `old` is `null` for added controls and `new`
+/// is `null` for removed ones.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct DiffRecord {
+    kind: String,
+    controls: Vec<String>,
+    old: Option<DiffLocation>,
+    new: Option<DiffLocation>,
+}
+
+impl From<&DiffEntry> for DiffRecord {
+    fn from(entry: &DiffEntry) -> DiffRecord {
+        let old = entry.get_old();
+        let new = entry.get_new();
+        let controls = new
+            .as_ref()
+            .or(old.as_ref())
+            .map(|commented_code| {
+                commented_code
+                    .get_controls()
+                    .iter()
+                    .map(|id| id.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        DiffRecord {
+            kind: entry.get_kind().to_string(),
+            controls,
+            old: old.as_ref().map(DiffLocation::from),
+            new: new.as_ref().map(DiffLocation::from),
+        }
+    }
+}
+
+/// Renders the changed entries of a diff in the given format. Unchanged entries are left out.
+pub fn render_diff(entries: &[DiffEntry], format: DiffFormat) -> Result<String, Error> {
+    let changes: Vec<&DiffEntry> = entries
+        .iter()
+        .filter(|entry| entry.get_kind() != DiffKind::Unchanged)
+        .collect();
+    match format {
+        DiffFormat::Text => Ok(render_diff_text(&changes)),
+        DiffFormat::Json => {
+            let records: Vec<DiffRecord> = changes.into_iter().map(DiffRecord::from).collect();
+            serde_json::to_string_pretty(&records)
+                .map(|json| json + "\n")
+                .map_err(|err| Error::Output(err.to_string()))
+        }
+        DiffFormat::Markdown => Ok(render_diff_markdown(&changes)),
+    }
+}
+
+fn render_diff_text(changes: &[&DiffEntry]) -> String {
+    if changes.is_empty() {
+        return "No changes detected.\n".to_string();
+    }
+
+    let location = |commented_code: CommentedCode| {
+        format!(
+            "{:?};{:?}:{:?}",
+            commented_code.get_path(),
+            commented_code.get_start().row + 1,
+            commented_code.get_end().row + 1
+        )
+    };
+    let mut output = "Changes Detected!\n ".to_string();
+    for entry in changes {
+        let line = match (entry.get_kind(), entry.get_old(), entry.get_new()) {
+            (DiffKind::Removed, Some(old), _) => format!("\t- {}\n", location(old)),
+            (DiffKind::Added, _, Some(new)) => format!("\t+ {}\n", location(new)),
+            (DiffKind::Modified, Some(old), Some(new)) => {
+                format!("\t~ {} -> {}\n", location(old), location(new))
+            }
+            (DiffKind::Moved, Some(old), Some(new)) => {
+                format!("\t> {} -> {}\n", location(old), location(new))
+            }
+            _ => continue,
+        };
+        output.push_str(&line);
+    }
+    output
+}
+
+fn render_diff_markdown(changes: &[&DiffEntry]) -> String {
+    if changes.is_empty() {
+        return "No control changes detected.\n".to_string();
+    }
+
+    let cell = |location: &Option<DiffLocation>| match location {
+        Some(location) => format!("`{}`", location),
+        None => "-".to_string(),
+    };
+    let mut output = format!(
+        "### {} control change(s)\n\n| Change | Controls | Old location | New location |\n|---|---|---|---|\n",
+        changes.len()
+    );
+    for entry in changes {
+        let record = DiffRecord::from(*entry);
+        output.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            record.kind,
+            record.controls.join(" "),
+            cell(&record.old),
+            cell(&record.new)
+        ));
+    }
+    output
+}
+
+/// Renders control log entries in the given format.
+pub fn render(commented_code: &[CommentedCode], format: LogFormat) -> Result<String, Error> {
+    let records: Vec<LogRecord> = commented_code.iter().map(LogRecord::from).collect();
+    match format {
+        LogFormat::Json => serde_json::to_string_pretty(&records)
+            .map(|json| json + "\n")
+            .map_err(|err| Error::Output(err.to_string())),
+        LogFormat::Yaml => {
+            serde_yaml::to_string(&records).map_err(|err| Error::Output(err.to_string()))
+        }
+        LogFormat::Csv => render_csv(&records),
+        LogFormat::Table => Ok(render_table(&records)),
+    }
+}
+
+fn render_csv(records: &[LogRecord]) -> Result<String, Error> {
+    let output_error = |err: csv::Error| Error::Output(err.to_string());
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(CSV_HEADER).map_err(output_error)?;
+    for record in records {
+        writer
+            .write_record([
+                record.path.clone(),
+                record.start_line.to_string(),
+                record.end_line.to_string(),
+                record.controls.join(" "),
+                record.attributes(),
+                record.justification.clone().unwrap_or_default(),
+                record.comment.clone(),
+                record.comment_hash.clone(),
+                record.content_hash.clone(),
+            ])
+            .map_err(output_error)?;
+    }
+    let bytes = writer
+        .into_inner()
+        .map_err(|err| Error::Output(err.to_string()))?;
+    String::from_utf8(bytes).map_err(|err| Error::Output(err.to_string()))
+}
+
+fn render_table(records: &[LogRecord]) -> String {
+    let mut rows = vec![TABLE_HEADER.map(String::from)];
+    for record in records {
+        rows.push([
+            record.path.clone(),
+            record.lines(),
+            record.controls.join(" "),
+            record.content_hash.chars().take(TABLE_HASH_LENGTH).collect(),
+        ]);
+    }
+
+    let mut widths = [0; TABLE_HEADER.len()];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut output = String::new();
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        output.push_str(cells.join("  ").trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+#[test]
+fn check_render() {
+    use crate::control::code::Point;
+    use crate::control::digest::sha256;
+    use crate::control::tag::ControlTag;
+    use std::path::PathBuf;
+
+    let comment = "/* control AC-2 AC-3 owner=iam\n * Checked by the gateway */";
+    let commented_code = vec![CommentedCode::new(
+        PathBuf::from("src/Login.java"),
+        comment.to_string(),
+        ControlTag::parse(comment).unwrap(),
+        "login();".to_string(),
+        Point::new(4, 8),
+        Point::new(4, 16),
+    )];
+    let hash = sha256("login();");
+
+    let json = render(&commented_code, LogFormat::Json).unwrap();
+    let records: Vec<LogRecord> = serde_json::from_str(&json).unwrap();
+    assert_eq!(records[0].get_path(), "src/Login.java");
+    assert_eq!(records[0].start_line, 5);
+    assert_eq!(records[0].controls, vec!["AC-2", "AC-3"]);
+    assert_eq!(records[0].get_content_hash(), hash);
+
+    let yaml = render(&commented_code, LogFormat::Yaml).unwrap();
+    let records: Vec<LogRecord> = serde_yaml::from_str(&yaml).unwrap();
+    assert_eq!(records[0].justification, Some("Checked by the gateway".to_string()));
+
+    let csv = render(&commented_code, LogFormat::Csv).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(
+        lines.next().unwrap(),
+        "path,start_line,end_line,controls,attributes,justification,comment,comment_hash,content_hash"
+    );
+    assert!(lines
+        .next()
+        .unwrap()
+        .starts_with("src/Login.java,5,5,AC-2 AC-3,owner=iam,Checked by the gateway,\"/* control AC-2"));
+
+    let table = render(&commented_code, LogFormat::Table).unwrap();
+    assert_eq!(
+        table,
+        format!(
+            "PATH            LINES  CONTROLS   CONTENT HASH\nsrc/Login.java  5-5    AC-2 AC-3  {}\n",
+            &hash[..TABLE_HASH_LENGTH]
+        )
+    );
+}
+
+#[test]
+fn check_render_diff() {
+    use crate::control::code::Point;
+    use crate::control::digest::sha256;
+    use crate::control::tag::ControlTag;
+    use std::path::PathBuf;
+
+    let entry = |comment: &str, content: &str, row: usize| {
+        CommentedCode::new(
+            PathBuf::from("src/app.js"),
+            comment.to_string(),
+            ControlTag::parse(comment).unwrap(),
+            content.to_string(),
+            Point::new(row, 0),
+            Point::new(row, content.len()),
+        )
+    };
+    let entries = vec![
+        DiffEntry::new(
+            DiffKind::Unchanged,
+            Some(entry("// control AC-1", "same();", 1)),
+            Some(entry("// control AC-1", "same();", 1)),
+        ),
+        DiffEntry::new(
+            DiffKind::Modified,
+            Some(entry("// control AC-2", "before();", 2)),
+            Some(entry("// control AC-2", "after();", 3)),
+        ),
+        DiffEntry::new(DiffKind::Added, None, Some(entry("// control AC-3", "fresh();", 5))),
+    ];
+
+    let json = render_diff(&entries, DiffFormat::Json).unwrap();
+    let records: Vec<DiffRecord> = serde_json::from_str(&json).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].kind, "modified");
+    assert_eq!(records[0].controls, vec!["AC-2"]);
+    assert_eq!(records[0].old.as_ref().unwrap().start_line, 3);
+    assert_eq!(records[0].old.as_ref().unwrap().content_hash, sha256("before();"));
+    assert_eq!(records[0].new.as_ref().unwrap().start_line, 4);
+    assert_eq!(records[0].new.as_ref().unwrap().content_hash, sha256("after();"));
+    assert_eq!(records[1].kind, "added");
+    assert_eq!(records[1].old, None);
+
+    assert_eq!(
+        render_diff(&entries, DiffFormat::Text).unwrap(),
+        "Changes Detected!\n \t~ \"src/app.js\";3:3 -> \"src/app.js\";4:4\n\t+ \"src/app.js\";6:6\n"
+    );
+    assert_eq!(
+        render_diff(&entries, DiffFormat::Markdown).unwrap(),
+        "### 2 control change(s)\n\n| Change | Controls | Old location | New location |\n|---|---|---|---|\n\
+         | modified | AC-2 | `src/app.js:3-3` | `src/app.js:4-4` |\n\
+         | added | AC-3 | - | `src/app.js:6-6` |\n"
+    );
+    assert_eq!(render_diff(&entries[..1], DiffFormat::Json).unwrap(), "[]\n");
+}
@@ -0,0 +1,382 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+const KEYWORD: &str = "control";
+const START_KEYWORD: &str = "control-start";
+const END_KEYWORD: &str = "control-end";
+const OPENING_DELIMITERS: [&str; 9] = [
+    "/**", "/*!", "/*", "///", "//!", "//", "#", "\"\"\"", "'''",
+];
+/// Characters of Python string prefixes such as `r`, `u` or `Rb`, which may precede a docstring.
+const STRING_PREFIXES: &str = "rRuUbBfF";
+const CLOSING_DELIMITERS: [&str; 4] = ["**/", "*/", "\"\"\"", "'''"];
+
+fn control_id_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"^[A-Z][A-Za-z0-9]*(?:[-.][A-Za-z0-9]+)*(?:\([A-Za-z0-9]+\))*$").unwrap()
+    })
+}
+
+fn attribute_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r#"^([A-Za-z_][A-Za-z0-9_.-]*)=(?:"([^"]*)"|(\S+))$"#).unwrap())
+}
+
+/// A single control identifier such as `HE-110`, `JS-1` or `AC-2(1)`.
+#[derive(Clone, Debug, Deserialize, Hash, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(transparent)]
+pub struct ControlId(String);
+
+impl ControlId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for ControlId {
+    type Err = String;
+
+    fn from_str(id: &str) -> Result<ControlId, String> {
+        if control_id_regex().is_match(id) {
+            Ok(ControlId(id.to_string()))
+        } else {
+            Err(format!("Invalid control id: {}", id))
+        }
+    }
+}
+
+impl fmt::Display for ControlId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Whether a control comment tags a single node or opens/closes a region.
+#[derive(Clone, Copy, Debug, Deserialize, Hash, Eq, PartialEq, Serialize)]
+pub enum Marker {
+    /// `control ...` attaches to the code that follows the comment.
+    Control,
+    /// `control-start ...` opens a region closed by a matching `control-end`.
+    Start,
+    /// `control-end ...` closes the region opened by a matching `control-start`.
+    End,
+}
+
+impl fmt::Display for Marker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Marker::Control => write!(f, "{}", KEYWORD),
+            Marker::Start => write!(f, "{}", START_KEYWORD),
+            Marker::End => write!(f, "{}", END_KEYWORD),
+        }
+    }
+}
+
+/// The parsed form of a control comment:
+///
+/// ```text
+/// control AC-2 AC-2(1) owner=iam-team status="partially implemented"
+/// Free-text justification, which may span
+/// any number of following lines.
+/// ```
+#[derive(Clone, Debug, Deserialize, Hash, Eq, PartialEq, Serialize)]
+pub struct ControlTag {
+    marker: Marker,
+    controls: Vec<ControlId>,
+    attributes: BTreeMap<String, String>,
+    justification: Option<String>,
+}
+
+impl ControlTag {
+    pub fn new(
+        marker: Marker,
+        controls: Vec<ControlId>,
+        attributes: BTreeMap<String, String>,
+        justification: Option<String>,
+    ) -> ControlTag {
+        ControlTag {
+            marker,
+            controls,
+            attributes,
+            justification,
+        }
+    }
+
+    /// Parses a raw comment, returning `None` when it is not a control tag.
+    pub fn parse(comment: &str) -> Option<ControlTag> {
+        let text = strip_comment_delimiters(comment);
+        let mut lines = text.lines();
+        let first_line = lines.next()?;
+        let mut tokens = tokenize(first_line).into_iter().peekable();
+        let marker = marker(tokens.next()?)?;
+
+        let mut controls: Vec<ControlId> = Vec::new();
+        while let Some(id) = tokens.peek().and_then(|token| token.parse().ok()) {
+            controls.push(id);
+            tokens.next();
+        }
+        if controls.is_empty() {
+            return None;
+        }
+
+        let mut attributes = BTreeMap::new();
+        while let Some(captures) = tokens.peek().and_then(|token| attribute_regex().captures(token)) {
+            let value = captures.get(2).or_else(|| captures.get(3)).unwrap();
+            attributes.insert(captures[1].to_string(), value.as_str().to_string());
+            tokens.next();
+        }
+
+        let remainder = tokens.collect::<Vec<&str>>().join(" ");
+        let justification = std::iter::once(remainder.as_str())
+            .chain(lines)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<&str>>()
+            .join("\n");
+
+        Some(ControlTag::new(
+            marker,
+            controls,
+            attributes,
+            Some(justification).filter(|justification| !justification.is_empty()),
+        ))
+    }
+
+    /// Parses a group of line comments on consecutive lines, such as `// control AC-2` followed by
+    /// `// Accounts are...`, whose later comments continue the justification of the first.
+    pub fn parse_group(comments: &[&str]) -> Option<ControlTag> {
+        let lines: Vec<String> =
+            comments.iter().map(|comment| strip_comment_delimiters(comment)).collect();
+        ControlTag::parse(&lines.join("\n"))
+    }
+
+    /// Parses a Python docstring, whose tag may follow a summary line as in
+    /// `"""Close the account.\n\ncontrol AC-3"""`, returning `None` when it has no control tag.
+    pub fn parse_docstring(docstring: &str) -> Option<ControlTag> {
+        let text = strip_comment_delimiters(docstring);
+        let lines: Vec<&str> = text.lines().collect();
+        let start = lines.iter().position(|line| marker(line).is_some())?;
+        ControlTag::parse(&lines[start..].join("\n"))
+    }
+
+    /// Explains why a comment that is not a control tag looks like one, e.g. because its control
+    /// ID is invalid or it does not start on the first line of the comment, so that such tags are
+    /// reported instead of silently dropped.
+    pub fn diagnose(comment: &str) -> Option<String> {
+        let text = strip_comment_delimiters(comment);
+        for (index, line) in text.lines().enumerate() {
+            let tokens = tokenize(line);
+            let (keyword, id) = match tokens.as_slice() {
+                [keyword, id, ..] if marker(keyword).is_some() => (keyword, id),
+                _ => continue,
+            };
+            if id.parse::<ControlId>().is_err() {
+                if id.chars().any(|character| character.is_ascii_digit()) {
+                    return Some(format!(
+                        "{} {} is ignored: control IDs start with an uppercase letter, e.g. AC-1",
+                        keyword, id
+                    ));
+                }
+            } else if index > 0 {
+                return Some(format!(
+                    "{} {} is ignored: control tags must start on the first line of a comment",
+                    keyword, id
+                ));
+            }
+        }
+        None
+    }
+
+    pub fn get_marker(&self) -> Marker {
+        self.marker
+    }
+
+    pub fn get_controls(&self) -> Vec<ControlId> {
+        self.controls.clone()
+    }
+
+    pub fn get_attributes(&self) -> BTreeMap<String, String> {
+        self.attributes.clone()
+    }
+
+    pub fn get_attribute(&self, key: &str) -> Option<String> {
+        self.attributes.get(key).cloned()
+    }
+
+    pub fn get_justification(&self) -> Option<String> {
+        self.justification.clone()
+    }
+}
+
+/// The marker a line starts with, if any.
+fn marker(line: &str) -> Option<Marker> {
+    match line.split_whitespace().next()? {
+        KEYWORD => Some(Marker::Control),
+        START_KEYWORD => Some(Marker::Start),
+        END_KEYWORD => Some(Marker::End),
+        _ => None,
+    }
+}
+
+/// Splits a line on whitespace, keeping double-quoted attribute values together.
+fn tokenize(line: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+    for (index, character) in line.char_indices() {
+        match character {
+            '"' => {
+                quoted = !quoted;
+                start.get_or_insert(index);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if let Some(start) = start.take() {
+                    tokens.push(&line[start..index]);
+                }
+            }
+            _ => {
+                start.get_or_insert(index);
+            }
+        }
+    }
+    if let Some(start) = start {
+        tokens.push(&line[start..]);
+    }
+    tokens
+}
+
+fn strip_string_prefix(text: &str) -> &str {
+    let prefix = text
+        .find(|character| !STRING_PREFIXES.contains(character))
+        .unwrap_or(0);
+    let quoted = &text[prefix..];
+    if prefix <= 2 && (quoted.starts_with("\"\"\"") || quoted.starts_with("'''")) {
+        quoted
+    } else {
+        text
+    }
+}
+
+/// Removes comment delimiters (`//`, `/*`, `*`, `**/`, `#`, `"""`, ...) and surrounding blank lines.
+pub fn strip_comment_delimiters(comment: &str) -> String {
+    let mut text = strip_string_prefix(comment.trim());
+    if let Some(delimiter) = OPENING_DELIMITERS.iter().find(|d| text.starts_with(*d)) {
+        text = &text[delimiter.len()..];
+    }
+    if let Some(delimiter) = CLOSING_DELIMITERS.iter().find(|d| text.ends_with(*d)) {
+        text = &text[..text.len() - delimiter.len()];
+    }
+
+    let lines: Vec<&str> = text
+        .lines()
+        .map(|line| line.trim().trim_start_matches('*').trim())
+        .collect();
+    let first = lines.iter().position(|line| !line.is_empty());
+    let last = lines.iter().rposition(|line| !line.is_empty());
+    match (first, last) {
+        (Some(first), Some(last)) => lines[first..=last].join("\n"),
+        _ => String::new(),
+    }
+}
+
+#[test]
+fn check_strip_comment_delimiters() {
+    assert_eq!(strip_comment_delimiters("// control SUB-1"), "control SUB-1");
+    assert_eq!(strip_comment_delimiters("/** control SUB-1 **/"), "control SUB-1");
+    assert_eq!(strip_comment_delimiters("/*\n * control D-3RP\n*/"), "control D-3RP");
+    assert_eq!(
+        strip_comment_delimiters("/**\n   * control T84\n   * second line\n  **/"),
+        "control T84\nsecond line"
+    );
+    assert_eq!(strip_comment_delimiters("# control PY-1"), "control PY-1");
+    assert_eq!(
+        strip_comment_delimiters("\"\"\"control PY-1\n\n    Validates input.\n    \"\"\""),
+        "control PY-1\n\nValidates input."
+    );
+    assert_eq!(strip_comment_delimiters("u\"\"\"control PY-1\"\"\""), "control PY-1");
+    assert_eq!(strip_comment_delimiters("Rb'''control PY-1'''"), "control PY-1");
+    assert_eq!(strip_comment_delimiters("//! control RS-1"), "control RS-1");
+    assert_eq!(strip_comment_delimiters("/*! control RS-1 */"), "control RS-1");
+}
+
+#[test]
+fn check_parse_group() {
+    let comments = ["// control AC-2 owner=iam", "// Accounts are", "//", "// provisioned."];
+    let tag = ControlTag::parse_group(&comments).unwrap();
+    assert_eq!(tag.get_controls(), vec!["AC-2".parse().unwrap()]);
+    assert_eq!(tag.get_attribute("owner"), Some("iam".to_string()));
+    assert_eq!(tag.get_justification(), Some("Accounts are\nprovisioned.".to_string()));
+    assert_eq!(ControlTag::parse_group(&["// Accounts are", "// control AC-2"]), None);
+}
+
+#[test]
+fn check_parse_control_tag() {
+    let tag = ControlTag::parse("/* control HE-110 JS-1 AC-2(1) */").unwrap();
+    let controls: Vec<&str> = tag.controls.iter().map(|id| id.as_str()).collect();
+    assert_eq!(controls, vec!["HE-110", "JS-1", "AC-2(1)"]);
+
+    assert_eq!(tag.marker, Marker::Control);
+    assert!(tag.attributes.is_empty());
+    assert!(tag.justification.is_none());
+
+    assert!(ControlTag::parse("// access control list").is_none());
+    assert!(ControlTag::parse("// control").is_none());
+    assert!(ControlTag::parse("// control-start").is_none());
+    assert!(ControlTag::parse("// just a comment").is_none());
+}
+
+#[test]
+fn check_parse_control_tag_attributes_and_justification() {
+    let comment = "/*\n * control AC-2 owner=iam-team status=\"partially implemented\"\n * Accounts are provisioned\n * through the IdP only.\n */";
+    let tag = ControlTag::parse(comment).unwrap();
+    assert_eq!(tag.get_controls(), vec!["AC-2".parse().unwrap()]);
+    assert_eq!(tag.get_attribute("owner"), Some("iam-team".to_string()));
+    assert_eq!(tag.get_attribute("status"), Some("partially implemented".to_string()));
+    assert_eq!(
+        tag.get_justification(),
+        Some("Accounts are provisioned\nthrough the IdP only.".to_string())
+    );
+
+    let tag = ControlTag::parse("// control AC-3 owner=iam enforced by middleware").unwrap();
+    assert_eq!(tag.get_attribute("owner"), Some("iam".to_string()));
+    assert_eq!(tag.get_justification(), Some("enforced by middleware".to_string()));
+}
+
+#[test]
+fn check_parse_region_markers() {
+    let start = ControlTag::parse("// control-start AC-3 owner=iam").unwrap();
+    assert_eq!(start.get_marker(), Marker::Start);
+    assert_eq!(start.get_controls(), vec!["AC-3".parse().unwrap()]);
+    assert_eq!(start.get_attribute("owner"), Some("iam".to_string()));
+
+    let end = ControlTag::parse("/* control-end AC-3 */").unwrap();
+    assert_eq!(end.get_marker(), Marker::End);
+    assert_eq!(end.get_controls(), start.get_controls());
+}
+
+#[test]
+fn check_parse_docstring() {
+    let tag = ControlTag::parse_docstring("\"\"\"Close the account.\n\n    control AC-3 owner=iam\n    \"\"\"").unwrap();
+    assert_eq!(tag.get_controls(), vec!["AC-3".parse().unwrap()]);
+    assert_eq!(tag.get_attribute("owner"), Some("iam".to_string()));
+    assert!(ControlTag::parse("\"\"\"Close the account.\n\n    control AC-3\n    \"\"\"").is_none());
+    assert!(ControlTag::parse_docstring("\"\"\"Close the account.\"\"\"").is_none());
+}
+
+#[test]
+fn check_diagnose_control_tag() {
+    assert_eq!(
+        ControlTag::diagnose("/**\n * Does X.\n * control AC-1\n */"),
+        Some("control AC-1 is ignored: control tags must start on the first line of a comment".to_string())
+    );
+    assert_eq!(
+        ControlTag::diagnose("// control ac-1"),
+        Some("control ac-1 is ignored: control IDs start with an uppercase letter, e.g. AC-1".to_string())
+    );
+    assert_eq!(ControlTag::diagnose("// control flow is handled below"), None);
+    assert_eq!(ControlTag::diagnose("// under version control"), None);
+}
@@ -0,0 +1,182 @@
+use crate::control::code::TagError;
+use crate::control::log::FORMAT_VERSION;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Stable process exit codes of the `control` binary, see the README.
+pub mod exit_code {
+    /// The command succeeded.
+    pub const SUCCESS: i32 = 0;
+    /// `--diff` detected changes to controlled code.
+    pub const DRIFT: i32 = 1;
+    /// The command line could not be parsed (reported by clap).
+    pub const USAGE: i32 = 2;
+    /// The configuration or the requested language is invalid.
+    pub const CONFIG: i32 = 3;
+    /// A parser plugin could not be downloaded or loaded.
+    pub const PLUGIN: i32 = 4;
+    /// Source code, a control comment or a control log could not be parsed.
+    pub const PARSE: i32 = 5;
+    /// Reading or writing a file failed.
+    pub const IO: i32 = 6;
+    /// The scan found no control comments.
+    pub const NO_CONTROLS: i32 = 7;
+}
+
+/// The stage at which decoding a control log failed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LogStage {
+    Decompress,
+    Header,
+    Body,
+}
+
+impl fmt::Display for LogStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogStage::Decompress => write!(f, "decompression"),
+            LogStage::Header => write!(f, "header"),
+            LogStage::Body => write!(f, "entries"),
+        }
+    }
+}
+
+/// Errors returned by the `control_cli` library.
+#[derive(Debug)]
+pub enum Error {
+    /// A parser plugin library could not be found or loaded.
+    PluginNotFound { path: PathBuf, message: String },
+    /// A parser plugin library was loaded but does not export the expected symbol.
+    SymbolMissing {
+        path: PathBuf,
+        symbol: String,
+        message: String,
+    },
+    /// A parser plugin could not be downloaded.
+    Download { url: String, message: String },
+    /// The requested language has no parser plugin.
+    UnsupportedLanguage(String),
+    /// The configuration could not be loaded, stored or is invalid.
+    Config(String),
+    /// Reading or writing a file failed.
+    Io { path: PathBuf, source: io::Error },
+    /// Source code could not be parsed.
+    Parse(String),
+    /// A control comment is malformed or misplaced.
+    Tag(TagError),
+    /// Control comments were skipped (e.g. had nothing to attach to or were ignored) and the scan was
+    /// strict.
+    Skipped(Vec<TagError>),
+    /// The scan found no control comments.
+    NoControls,
+    /// A control log does not exist.
+    MissingLog(PathBuf),
+    /// The control log to diff against does not exist.
+    MissingBaseline(PathBuf),
+    /// A control log could not be decoded.
+    LogCorrupt { stage: LogStage, message: String },
+    /// A control log was written in a format version this build cannot read.
+    UnsupportedLogVersion(u32),
+    /// Output could not be rendered in the requested format.
+    Output(String),
+}
+
+impl Error {
+    pub fn io(path: PathBuf, source: io::Error) -> Error {
+        Error::Io { path, source }
+    }
+
+    pub fn log_corrupt(stage: LogStage, message: impl ToString) -> Error {
+        Error::LogCorrupt {
+            stage,
+            message: message.to_string(),
+        }
+    }
+
+    /// The exit code the `control` binary reports for this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::UnsupportedLanguage(_) | Error::Config(_) => exit_code::CONFIG,
+            Error::PluginNotFound { .. } | Error::SymbolMissing { .. } | Error::Download { .. } => {
+                exit_code::PLUGIN
+            }
+            Error::Parse(_)
+            | Error::Tag(_)
+            | Error::Skipped(_)
+            | Error::LogCorrupt { .. }
+            | Error::UnsupportedLogVersion(_) => exit_code::PARSE,
+            Error::Io { .. } | Error::MissingLog(_) | Error::MissingBaseline(_) | Error::Output(_) => {
+                exit_code::IO
+            }
+            Error::NoControls => exit_code::NO_CONTROLS,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::PluginNotFound { path, message } => {
+                write!(f, "Could not load parser plugin {}: {}", path.display(), message)
+            }
+            Error::SymbolMissing {
+                path,
+                symbol,
+                message,
+            } => write!(
+                f,
+                "Parser plugin {} does not export {}: {}",
+                path.display(),
+                symbol,
+                message
+            ),
+            Error::Download { url, message } => {
+                write!(f, "Could not download parser plugin {}: {}", url, message)
+            }
+            Error::UnsupportedLanguage(language) => write!(f, "Unsupported language: {}", language),
+            Error::Config(message) => write!(f, "Invalid configuration: {}", message),
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Parse(message) => write!(f, "Could not parse source: {}", message),
+            Error::Tag(err) => write!(f, "{}", err),
+            Error::Skipped(findings) => write!(
+                f,
+                "{} control comment(s) were skipped.",
+                findings.len()
+            ),
+            Error::NoControls => write!(f, "No commented code found."),
+            Error::MissingLog(path) => write!(f, "No control log found at {}", path.display()),
+            Error::MissingBaseline(path) => write!(
+                f,
+                "No baseline found at {} (pass --allow-missing-baseline to treat every control as added)",
+                path.display()
+            ),
+            Error::LogCorrupt { stage, message } => {
+                write!(f, "Corrupt control log ({} failed): {}", stage, message)
+            }
+            Error::UnsupportedLogVersion(version) => write!(
+                f,
+                "Control log format version {} is not supported (this build reads versions 0 to {}); upgrade control",
+                version,
+                FORMAT_VERSION
+            ),
+            Error::Output(message) => write!(f, "Could not render output: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Tag(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<TagError> for Error {
+    fn from(err: TagError) -> Error {
+        Error::Tag(err)
+    }
+}
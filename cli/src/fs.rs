@@ -1,6 +1,12 @@
+use crate::control::log::{ControlLog, FORMAT_VERSION, MAGIC};
+use crate::error::{Error, LogStage};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::WalkBuilder;
 use std::fs::File;
-use std::io::Read;
-use std::path::PathBuf;
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Component, Path, PathBuf};
 
 pub struct ReadFile {
     path: PathBuf,
@@ -21,80 +27,436 @@ impl ReadFile {
     }
 }
 
-pub fn decompress_file(path: PathBuf) -> Vec<u8> {
-    let mut file = match File::open(path) {
-        Ok(file) => file,
-        Err(_) => return Vec::new(),
-    };
+pub fn decompress_file(path: PathBuf) -> Result<Vec<u8>, Error> {
+    let mut file = File::open(&path).map_err(|err| match err.kind() {
+        ErrorKind::NotFound => Error::MissingLog(path.clone()),
+        _ => Error::io(path.clone(), err),
+    })?;
 
     let mut contents = Vec::new();
+    file.read_to_end(&mut contents)
+        .map_err(|err| Error::io(path.clone(), err))?;
 
-    return match file.read_to_end(&mut contents) {
-        Ok(_) => {
-            let mut decoder = brotli::Decompressor::new(&contents[..], 4096);
-            let mut decompressed = Vec::new();
-            match decoder.read_to_end(&mut decompressed) {
-                Ok(_) => decompressed,
-                Err(_) => Vec::new(),
-            }
-        }
-        Err(_) => Vec::new(),
-    };
+    let mut decoder = brotli::Decompressor::new(&contents[..], 4096);
+    let mut decompressed = Vec::new();
+    decoder
+        .read_to_end(&mut decompressed)
+        .map_err(|err| Error::log_corrupt(LogStage::Decompress, err))?;
+    Ok(decompressed)
 }
 
-#[allow(dead_code)]
-pub fn read_file(path: PathBuf) -> Option<Vec<ReadFile>> {
-    let mut file = match File::open(path.clone()) {
-        Ok(file) => file,
-        Err(_) => return None,
+/// Reads a `.control-log`, including logs written in older format versions.
+pub fn read_log(path: PathBuf) -> Result<ControlLog, Error> {
+    let decompressed = decompress_file(path)?;
+    let versioned = match decompressed.strip_prefix(&MAGIC) {
+        Some(versioned) => versioned,
+        None => return ControlLog::decode_v0(&decompressed),
     };
+    if versioned.len() < 4 {
+        return Err(Error::log_corrupt(LogStage::Header, "truncated format version"));
+    }
+
+    let (version, body) = versioned.split_at(4);
+    match u32::from_le_bytes([version[0], version[1], version[2], version[3]]) {
+        1 => ControlLog::decode_v1(body),
+        2 => ControlLog::decode_v2(body),
+        3 => ControlLog::decode_v3(body),
+        version if version > FORMAT_VERSION => Err(Error::UnsupportedLogVersion(version)),
+        version => Err(Error::log_corrupt(
+            LogStage::Header,
+            format!("unknown format version {}", version),
+        )),
+    }
+}
+
+/// Writes a brotli-compressed `.control-log` in the current format version.
+pub fn write_log(path: PathBuf, log: &ControlLog) -> Result<(), Error> {
+    let bytes = log.encode()?;
+    let file = File::create(&path).map_err(|err| Error::io(path.clone(), err))?;
+    let mut file_writer = brotli::CompressorWriter::new(file, 4096, 5, 22);
+    file_writer
+        .write_all(&bytes)
+        .map_err(|err| Error::io(path.clone(), err))?;
+    file_writer.flush().map_err(|err| Error::io(path, err))
+}
+
+pub fn read_file(path: PathBuf) -> Result<Vec<ReadFile>, Error> {
+    let mut file = File::open(&path).map_err(|err| Error::io(path.clone(), err))?;
     let mut contents = String::new();
-    return match file.read_to_string(&mut contents) {
-        Ok(_) => Some(Vec::from([ReadFile::new(path.clone(), contents)])),
-        Err(_) => None,
+    file.read_to_string(&mut contents)
+        .map_err(|err| Error::io(path.clone(), err))?;
+    Ok(Vec::from([ReadFile::new(path, contents)]))
+}
+
+/// Selects the files a scan reads.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FileFilter {
+    extensions: Vec<String>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    ignore_files: bool,
+}
+
+impl FileFilter {
+    /// Selects files with one of the given extensions that are not ignored by `.gitignore` or
+    /// `.ignore` files.
+    pub fn new(extensions: Vec<String>) -> FileFilter {
+        FileFilter {
+            extensions,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            ignore_files: true,
+        }
+    }
+
+    /// Only selects files matching one of the globs, relative to the scan root.
+    pub fn with_include(self, include: Vec<String>) -> FileFilter {
+        FileFilter { include, ..self }
+    }
+
+    /// Skips files and directories matching one of the globs, relative to the scan root.
+    pub fn with_exclude(self, exclude: Vec<String>) -> FileFilter {
+        FileFilter { exclude, ..self }
+    }
+
+    /// Whether `.gitignore` and `.ignore` files are honoured.
+    pub fn with_ignore_files(self, ignore_files: bool) -> FileFilter {
+        FileFilter {
+            ignore_files,
+            ..self
+        }
+    }
+
+    pub fn get_extensions(&self) -> Vec<String> {
+        self.extensions.clone()
+    }
+
+    pub fn get_include(&self) -> Vec<String> {
+        self.include.clone()
+    }
+
+    pub fn get_exclude(&self) -> Vec<String> {
+        self.exclude.clone()
+    }
+
+    pub fn get_ignore_files(&self) -> bool {
+        self.ignore_files
+    }
+
+    /// Skips `.git` directories, which the walk would otherwise enter as it includes hidden files,
+    /// and the excluded globs.
+    fn overrides(&self, root: &Path) -> Result<Override, Error> {
+        let mut overrides = OverrideBuilder::new(root);
+        overrides
+            .add("!.git/")
+            .map_err(|err| Error::Config(err.to_string()))?;
+        for glob in &self.exclude {
+            overrides
+                .add(&format!("!{}", glob))
+                .map_err(|err| invalid_glob(glob, err))?;
+        }
+        overrides.build().map_err(|err| Error::Config(err.to_string()))
+    }
+
+    /// The included globs, or `None` to include every file. These are not walk overrides, as a
+    /// whitelisting override would also select files ignored by ignore files.
+    fn includes(&self, root: &Path) -> Result<Option<Gitignore>, Error> {
+        if self.include.is_empty() {
+            return Ok(None);
+        }
+        let mut includes = GitignoreBuilder::new(root);
+        for glob in &self.include {
+            includes
+                .add_line(None, glob)
+                .map_err(|err| invalid_glob(glob, err))?;
+        }
+        includes
+            .build()
+            .map(Some)
+            .map_err(|err| Error::Config(err.to_string()))
+    }
+
+    fn matches_extension(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| self.extensions.iter().any(|ext| ext == extension))
+    }
+}
+
+fn invalid_glob(glob: &str, err: ignore::Error) -> Error {
+    Error::Config(format!("Invalid glob {}: {}", glob, err))
+}
+
+/// Returns `path` relative to the scan root `root` with `/` separators, so that logs generated on
+/// different machines and operating systems are identical. When `root` is the file itself, that is
+/// its file name.
+pub fn relative_path(root: &Path, path: &Path) -> PathBuf {
+    let relative = match path.strip_prefix(root) {
+        Ok(relative) if relative.as_os_str().is_empty() => {
+            path.file_name().map(Path::new).unwrap_or(path)
+        }
+        Ok(relative) => relative,
+        Err(_) => path,
     };
+    let components: Vec<String> = relative
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            Component::ParentDir => Some("..".to_string()),
+            _ => None,
+        })
+        .collect();
+    PathBuf::from(components.join("/"))
 }
 
-#[allow(dead_code)]
-pub fn read_dir<T>(path: PathBuf, ext: &Vec<String>, f: fn(PathBuf) -> Option<Vec<T>>) -> Vec<T> {
+/// Walks `path` and applies `f` to every file selected by `filter`. A missing `path` has no files.
+pub fn read_dir<T>(
+    path: PathBuf,
+    filter: &FileFilter,
+    f: fn(PathBuf) -> Result<Vec<T>, Error>,
+) -> Result<Vec<T>, Error> {
     let mut contents = Vec::new();
-    if path.is_dir() {
-        for entry in path.read_dir().unwrap() {
-            let entry = entry.unwrap();
-            let path = entry.path();
-            let mut child_contents: Vec<T> = read_dir(path, &ext, f);
-            contents.append(&mut child_contents);
-        }
-    } else {
-        let path_extension = path.extension().unwrap_or("".as_ref()).to_str();
-        if path_extension.is_some() && ext.contains(&path_extension.unwrap().to_string()) {
-            let mut child_contents: Vec<T> = f(path).unwrap();
+    if !path.exists() {
+        return Ok(contents);
+    }
+
+    let includes = filter.includes(&path)?;
+    let walk = WalkBuilder::new(&path)
+        .hidden(false)
+        .parents(filter.ignore_files)
+        .ignore(filter.ignore_files)
+        .git_ignore(filter.ignore_files)
+        .git_global(filter.ignore_files)
+        .git_exclude(filter.ignore_files)
+        .require_git(false)
+        .follow_links(true)
+        .overrides(filter.overrides(&path)?)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+    for entry in walk {
+        let entry = entry.map_err(|err| Error::io(path.clone(), io::Error::other(err)))?;
+        let is_file = entry.file_type().is_some_and(|file_type| file_type.is_file());
+        let included = includes.as_ref().is_none_or(|includes| {
+            includes
+                .matched_path_or_any_parents(entry.path(), false)
+                .is_ignore()
+        });
+        if is_file && included && filter.matches_extension(entry.path()) {
+            let mut child_contents: Vec<T> = f(entry.into_path())?;
             contents.append(&mut child_contents);
         }
     }
 
-    return contents;
+    Ok(contents)
 }
 
 #[test]
 fn check_decompress_file() {
     let path = PathBuf::from("tests/fixtures/.control-log.java.br");
-    let contents = decompress_file(path);
+    let contents = decompress_file(path).unwrap();
     assert_eq!(String::from_utf8(contents).unwrap(), "\u{1}\0\0\0\0\0\0\0%\0\0\0\0\0\0\0cli/tests/resources/java/Program.java\u{14}\0\0\0\0\0\0\0/* control HE-110 */$\0\0\0\0\0\0\0System.out.println(\"Hello, World!\");\u{5}\0\0\0\0\0\0\0\u{8}\0\0\0\0\0\0\0\u{5}\0\0\0\0\0\0\0,\0\0\0\0\0\0\0".to_string());
 }
 
+#[test]
+fn check_read_log_version_0() {
+    let path = PathBuf::from("tests/fixtures/.control-log.java.br");
+    let log = read_log(path).unwrap();
+    assert_eq!(log.get_format_version(), 0);
+    let commented_code = log.get_commented_code();
+    assert_eq!(commented_code.len(), 1);
+    assert_eq!(commented_code[0].get_comment(), "/* control HE-110 */");
+    assert_eq!(commented_code[0].get_controls(), vec!["HE-110".parse().unwrap()]);
+    assert_eq!(
+        commented_code[0].get_content().unwrap(),
+        "System.out.println(\"Hello, World!\");"
+    );
+}
+
+#[test]
+fn check_write_and_read_log() {
+    use crate::control::log::LogHeader;
+    let dir = assert_fs::TempDir::new().unwrap();
+    let path = dir.path().join(".control-log");
+    let header = LogHeader::new("java".to_string(), vec!["java".to_string()], "next".to_string());
+    let legacy = read_log(PathBuf::from("tests/fixtures/.control-log.java.br")).unwrap();
+    let log = ControlLog::new(header, legacy.into_commented_code());
+
+    write_log(path.clone(), &log).unwrap();
+    let read = read_log(path).unwrap();
+    assert_eq!(read, log);
+    assert_eq!(read.get_format_version(), FORMAT_VERSION);
+    assert_eq!(read.get_header().get_tool_version(), env!("CARGO_PKG_VERSION"));
+}
+
+#[test]
+fn check_read_log_version_1() {
+    use crate::control::log::LogHeader;
+    let dir = assert_fs::TempDir::new().unwrap();
+    let path = dir.path().join(".control-log");
+    let legacy = read_log(PathBuf::from("tests/fixtures/.control-log.java.br")).unwrap();
+    let v1_entries: Vec<_> = legacy
+        .get_commented_code()
+        .iter()
+        .map(|code| {
+            (
+                code.get_path(),
+                code.get_comment(),
+                code.get_tag(),
+                code.get_content().unwrap(),
+                code.get_start(),
+                code.get_end(),
+            )
+        })
+        .collect();
+    let header = LogHeader::new("java".to_string(), vec!["java".to_string()], "next".to_string());
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&1u32.to_le_bytes());
+    bytes.extend_from_slice(&bincode::serialize(&(&header, &v1_entries)).unwrap());
+    let file = File::create(&path).unwrap();
+    let mut file_writer = brotli::CompressorWriter::new(file, 4096, 5, 22);
+    file_writer.write_all(&bytes).unwrap();
+    drop(file_writer);
+
+    let log = read_log(path).unwrap();
+    assert_eq!(log.get_format_version(), 1);
+    assert_eq!(log.get_commented_code(), legacy.get_commented_code());
+}
+
+#[test]
+fn check_write_log_without_content() {
+    use crate::control::code::CommentedCode;
+    use crate::control::log::LogHeader;
+    let dir = assert_fs::TempDir::new().unwrap();
+    let path = dir.path().join(".control-log");
+    let header = LogHeader::new("java".to_string(), vec!["java".to_string()], "next".to_string());
+    let legacy = read_log(PathBuf::from("tests/fixtures/.control-log.java.br")).unwrap();
+    let commented_code: Vec<CommentedCode> = legacy
+        .get_commented_code()
+        .into_iter()
+        .map(CommentedCode::without_content)
+        .collect();
+
+    write_log(path.clone(), &ControlLog::new(header, commented_code)).unwrap();
+    let read = read_log(path).unwrap().into_commented_code();
+    assert_eq!(read[0].get_content(), None);
+    assert_eq!(read, legacy.get_commented_code());
+}
+
+#[test]
+fn check_read_log_unknown_version() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let path = dir.path().join(".control-log");
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+    let file = File::create(&path).unwrap();
+    let mut file_writer = brotli::CompressorWriter::new(file, 4096, 5, 22);
+    file_writer.write_all(&bytes).unwrap();
+    drop(file_writer);
+
+    match read_log(path) {
+        Err(Error::UnsupportedLogVersion(version)) => assert_eq!(version, FORMAT_VERSION + 1),
+        _ => panic!("expected an unsupported log version error"),
+    }
+}
+
+#[test]
+fn check_read_log_missing_or_corrupt() {
+    use assert_fs::prelude::*;
+    let dir = assert_fs::TempDir::new().unwrap();
+    match read_log(dir.path().join(".control-log")) {
+        Err(Error::MissingLog(path)) => assert_eq!(path, dir.path().join(".control-log")),
+        _ => panic!("expected a missing log error"),
+    }
+
+    let not_brotli = dir.child("not-brotli");
+    not_brotli.write_binary(&[0xff; 64]).unwrap();
+    match read_log(not_brotli.path().to_path_buf()) {
+        Err(Error::LogCorrupt { stage, .. }) => assert_eq!(stage, LogStage::Decompress),
+        _ => panic!("expected a decompression error"),
+    }
+
+    let truncated = dir.path().join("truncated");
+    let file = File::create(&truncated).unwrap();
+    let mut file_writer = brotli::CompressorWriter::new(file, 4096, 5, 22);
+    file_writer.write_all(&MAGIC).unwrap();
+    drop(file_writer);
+    match read_log(truncated) {
+        Err(Error::LogCorrupt { stage, .. }) => assert_eq!(stage, LogStage::Header),
+        _ => panic!("expected a header error"),
+    }
+}
+
 #[test]
 fn check_read_path() {
     let path = PathBuf::from("..");
-    let extensions = vec!["rs".to_string()];
-    let contents = read_dir(path, &extensions, read_file);
-    assert!(contents.len() > 0);
+    let filter = FileFilter::new(vec!["rs".to_string()]);
+    let contents = read_dir(path, &filter, read_file).unwrap();
+    assert!(!contents.is_empty());
+}
+
+#[test]
+fn check_relative_path() {
+    let root = Path::new("./tests/resources");
+    assert_eq!(
+        relative_path(root, &root.join("js").join("index.js")),
+        PathBuf::from("js/index.js")
+    );
+    assert_eq!(
+        relative_path(&root.join("java/Program.java"), &root.join("java/Program.java")),
+        PathBuf::from("Program.java")
+    );
+}
+
+#[test]
+fn check_read_dir_filters() {
+    use assert_fs::prelude::*;
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child(".gitignore").write_str("node_modules/\n").unwrap();
+    dir.child("node_modules/lib/index.js").touch().unwrap();
+    dir.child("src/app.js").touch().unwrap();
+    dir.child("src/app.test.js").touch().unwrap();
+    dir.child("src/vendor/lib.js").touch().unwrap();
+    dir.child("scripts/build.js").touch().unwrap();
+    dir.child("src/node_modules/lib/index.js").touch().unwrap();
+    dir.child(".git/hooks/pre-commit.js").touch().unwrap();
+    let read = |filter: FileFilter| -> Vec<String> {
+        read_dir(dir.path().to_path_buf(), &filter, |path| Ok(vec![path]))
+            .unwrap()
+            .iter()
+            .map(|path| path.strip_prefix(dir.path()).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect()
+    };
+    let filter = FileFilter::new(vec!["js".to_string()]);
+
+    assert_eq!(
+        read(filter.clone()),
+        vec!["scripts/build.js", "src/app.js", "src/app.test.js", "src/vendor/lib.js"]
+    );
+    assert_eq!(read(filter.clone().with_ignore_files(false)).len(), 6);
+    assert_eq!(
+        read(filter.clone().with_include(vec!["src/**".to_string()])),
+        vec!["src/app.js", "src/app.test.js", "src/vendor/lib.js"]
+    );
+    assert_eq!(
+        read(
+            filter
+                .clone()
+                .with_include(vec!["src/**".to_string()])
+                .with_exclude(vec!["*.test.js".to_string(), "vendor/".to_string()])
+        ),
+        vec!["src/app.js"]
+    );
+    let filter = filter.with_include(vec!["src/{a".to_string()]);
+    match read_dir(dir.path().to_path_buf(), &filter, |path| Ok(vec![path])) {
+        Err(Error::Config(message)) => assert!(message.starts_with("Invalid glob src/{a")),
+        _ => panic!("expected an invalid glob error"),
+    }
 }
 
 #[test]
 fn read_file_test() {
     let path = PathBuf::from("src/fs.rs");
     let content = read_file(path);
-    assert!(content.is_some());
-    assert!(content.unwrap().len() > 0);
+    assert!(content.is_ok());
+    assert!(!content.unwrap().is_empty());
 }
@@ -3,3 +3,6 @@ extern crate core;
 pub mod parser;
 pub mod fs;
 pub mod control;
+pub mod error;
+
+pub use error::Error;
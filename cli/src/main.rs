@@ -3,13 +3,23 @@ use std::fs::{File, remove_file};
 use std::io::{Cursor, Write};
 use clap::{Args, Parser, Subcommand};
 use serde_derive::{Serialize, Deserialize};
+use std::collections::BTreeSet;
 use std::path::PathBuf;
 use std::process::exit;
 use reqwest::Url;
+use control_cli::control::capture::Capture;
 use control_cli::control::code;
-use control_cli::control::code::{CommentedCode, get_common_values};
+use control_cli::control::code::CommentedCode;
+use control_cli::control::diff;
+use control_cli::control::diff::DiffKind;
+use control_cli::control::fingerprint::Comparison;
+use control_cli::control::log as control_log;
 use control_cli::fs;
-use control_cli::parser::SupportedLanguage;
+use control_cli::parser::{LanguageMap, SupportedLanguage};
+use control_cli::control::report;
+use control_cli::control::report::{DiffFormat, LogFormat};
+use control_cli::error::exit_code;
+use control_cli::Error;
 
 #[derive(Debug, Parser)]
 #[command(name = "control")]
@@ -70,6 +80,8 @@ enum ControlCommands {
     Parser(ControlParser),
     /// Print the control log
     Log(ControlLog),
+    /// Diff two control logs without scanning source
+    Diff(ControlDiff),
 }
 
 #[derive(Debug, Args)]
@@ -77,18 +89,52 @@ struct ControlCode {
     /// Source code directory
     #[arg(required = true)]
     directory: PathBuf,
-    /// Supported programming language
-    #[arg(long, required = true)]
-    lang: String,
-    /// File extension(s)
-    #[arg(long, required = true)]
+    /// Parse every scanned file as this language instead of by its extension
+    #[arg(long)]
+    lang: Option<String>,
+    /// File extension(s) to scan [default: those of --lang, or all mapped extensions]
+    #[arg(long)]
     ext: Vec<String>,
+    /// Parse files with extension EXT as LANG, e.g. `--map cjs=js` (repeatable)
+    #[arg(long, value_name = "EXT=LANG")]
+    map: Vec<String>,
     /// Output file path for control log
     #[arg(short, long, default_value = ".control-log")]
     output_file: PathBuf,
     /// Diff the generated hash with the one in the output file
     #[arg(long, action)]
     diff: bool,
+    /// Code captured by a control comment (next, function, class or file),
+    /// unless overridden by a `capture=` attribute on the comment
+    #[arg(long, default_value_t = Capture::Next)]
+    capture: Capture,
+    /// Fail when a control comment is skipped, e.g. because it has nothing to attach to
+    #[arg(long, action)]
+    strict: bool,
+    /// With --diff, treat every control as added when the output file does not exist
+    #[arg(long, action, requires = "diff")]
+    allow_missing_baseline: bool,
+    /// With --diff, output format (text, json or markdown)
+    #[arg(long, default_value_t = DiffFormat::Text, requires = "diff")]
+    diff_format: DiffFormat,
+    /// With --diff, compare code exactly instead of ignoring whitespace and formatting
+    #[arg(long, action, requires = "diff")]
+    exact: bool,
+    /// With --diff, also ignore changes to comments that are not control comments
+    #[arg(long, action, requires = "diff", conflicts_with = "exact")]
+    ignore_comments: bool,
+    /// Only scan files matching the glob (relative to the directory, repeatable)
+    #[arg(long)]
+    include: Vec<String>,
+    /// Skip files and directories matching the glob (relative to the directory, repeatable)
+    #[arg(long)]
+    exclude: Vec<String>,
+    /// Also scan files ignored by .gitignore and .ignore files
+    #[arg(long, action)]
+    no_ignore: bool,
+    /// Store only digests of the captured code in the control log, not the code itself
+    #[arg(long, action, conflicts_with = "diff")]
+    omit_content: bool,
 }
 
 #[derive(Debug, Args)]
@@ -117,6 +163,28 @@ struct ControlLog {
     /// File path for control log
     #[arg(short, long, default_value = ".control-log")]
     log_path: PathBuf,
+    /// Output format (json, yaml, csv or table)
+    #[arg(short, long, default_value_t = LogFormat::Json)]
+    format: LogFormat,
+}
+
+#[derive(Debug, Args)]
+struct ControlDiff {
+    /// Baseline control log
+    #[arg(required = true)]
+    old_log: PathBuf,
+    /// Control log to compare against the baseline
+    #[arg(required = true)]
+    new_log: PathBuf,
+    /// Output format (text, json or markdown)
+    #[arg(long, default_value_t = DiffFormat::Text)]
+    diff_format: DiffFormat,
+    /// Compare code exactly instead of ignoring whitespace and formatting
+    #[arg(long, action)]
+    exact: bool,
+    /// Also ignore changes to comments that are not control comments
+    #[arg(long, action, conflicts_with = "exact")]
+    ignore_comments: bool,
 }
 
 #[derive(Default, Debug, Serialize, Deserialize)]
@@ -125,9 +193,80 @@ struct Config {
     download_path: String,
 }
 
-fn main() -> Result<(), std::io::Error> {
+fn main() {
     let args = Cli::parse();
-    let mut config: Config = confy::load("control", "config").unwrap();
+    match run(args) {
+        Ok(code) => exit(code),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            exit(err.exit_code());
+        }
+    }
+}
+
+fn config_path() -> Result<PathBuf, Error> {
+    confy::get_configuration_file_path("control", "config").map_err(|err| Error::Config(err.to_string()))
+}
+
+fn download(url: &Url, filename: &str) -> Result<(), Error> {
+    let download_error = |err: reqwest::Error| Error::Download { url: url.to_string(), message: err.to_string() };
+    let response = reqwest::blocking::get(url.clone()).map_err(download_error)?;
+    let bytes = response.bytes().map_err(download_error)?;
+    let mut archive = File::create(filename).map_err(|err| Error::io(PathBuf::from(filename), err))?;
+    archive.write_all(&bytes).map_err(|err| Error::io(PathBuf::from(filename), err))
+}
+
+fn comparison(exact: bool, ignore_comments: bool) -> Comparison {
+    if exact {
+        Comparison::Exact
+    } else {
+        Comparison::Normalized { ignore_comments }
+    }
+}
+
+/// Resolves the extensions to scan and the language each is parsed as from `--lang`, `--ext` and
+/// `--map`.
+fn languages(code: &ControlCode) -> Result<(LanguageMap, Vec<String>), Error> {
+    let mut languages = LanguageMap::default();
+    for mapping in &code.map {
+        let (extension, language) = mapping
+            .split_once('=')
+            .ok_or_else(|| Error::Config(format!("Invalid mapping: {} (expected EXT=LANG)", mapping)))?;
+        languages = languages.with_language(extension.to_string(), language.parse()?);
+    }
+
+    let extensions = match &code.lang {
+        Some(lang) => {
+            let language = lang.parse::<SupportedLanguage>()?;
+            let extensions = if code.ext.is_empty() { languages.get_extensions_of(language) } else { code.ext.clone() };
+            for extension in &extensions {
+                languages = languages.with_language(extension.clone(), language);
+            }
+            extensions
+        }
+        None if code.ext.is_empty() => languages.get_extensions(),
+        None => code.ext.clone(),
+    };
+    if let Some(extension) = extensions.iter().find(|extension| languages.get_language(extension).is_none()) {
+        return Err(Error::Config(format!("No language for extension {} (pass --lang or --map {}=LANG)", extension, extension)));
+    }
+    Ok((languages, extensions))
+}
+
+/// Prints the changes between two sets of commented code, returning the process exit code.
+fn print_diff(old: Vec<CommentedCode>, new: Vec<CommentedCode>, comparison: Comparison, format: DiffFormat) -> Result<i32, Error> {
+    let entries = diff::diff(old, new, comparison);
+    print!("{}", report::render_diff(&entries, format)?);
+    if entries.iter().any(|entry| entry.get_kind() != DiffKind::Unchanged) {
+        Ok(exit_code::DRIFT)
+    } else {
+        Ok(exit_code::SUCCESS)
+    }
+}
+
+/// Runs the command, returning the process exit code.
+fn run(args: Cli) -> Result<i32, Error> {
+    let mut config: Config = confy::load("control", "config").map_err(|err| Error::Config(err.to_string()))?;
 
     match args.command {
         Commands::Config(cli_config) => {
@@ -143,14 +282,12 @@ fn main() -> Result<(), std::io::Error> {
                         "download_path" => {
                             config.download_path = value;
                         }
-                        _ => clap::Error::raw(clap::error::ErrorKind::Io, format!("Invalid field: {}", field)).exit()
-
+                        _ => return Err(Error::Config(format!("Invalid field: {}", field)))
                     }
-                    confy::store("control", "config", config).unwrap();
+                    confy::store("control", "config", config).map_err(|err| Error::Config(err.to_string()))?;
                 },
                 ConfigCommands::Path => {
-                    let config_path = confy::get_configuration_file_path("control", "config").unwrap();
-                    println!("{}", config_path.to_str().unwrap());
+                    println!("{}", config_path()?.display());
                 }
             }
         }
@@ -161,131 +298,98 @@ fn main() -> Result<(), std::io::Error> {
                     let parser_cmd = parser.command.unwrap();
                     match parser_cmd {
                         ControlParserCommands::Download(cmd) => {
-                            let storage_path = match confy::get_configuration_file_path("control", "config") {
-                                Ok(path) => {
-                                    match path.parent() {
-                                        Some(path) => {
-                                            path.join(format!("libparser_{}.{}", cmd.lang, env::consts::DLL_EXTENSION))
-                                        }
-                                        None => clap::Error::raw(clap::error::ErrorKind::Io, "Could not get parent directory of config file").exit()
-                                    }
+                            let storage_path = match config_path()?.parent() {
+                                Some(path) => {
+                                    path.join(format!("libparser_{}.{}", cmd.lang, env::consts::DLL_EXTENSION))
                                 }
-                                Err(err) => clap::Error::raw(clap::error::ErrorKind::Io, err).exit()
+                                None => return Err(Error::Config("Could not get parent directory of config file".to_string()))
                             };
 
-                            match fs::read_file(storage_path.clone()) {
-                                Some(_) => {
-                                    println!("Parser already exists");
+                            if storage_path.exists() {
+                                println!("Parser already exists");
+                            } else {
+                                let arch = if env::consts::OS == "windows" {
+                                    "x86_64-pc-windows-msvc"
+                                } else if env::consts::OS == "macos" {
+                                    "x86_64-apple-darwin"
+                                } else {
+                                    "x86_64-unknown-linux-gnu"
+                                };
+                                let archive_ext = if env::consts::OS == "windows" { "zip" } else { "tar.gz" };
+                                let filename = format!("libparser_{}-{}.{}", cmd.lang, arch, archive_ext);
+                                let url = Url::parse(&config.host)
+                                    .and_then(|url| url.join(&config.download_path))
+                                    .and_then(|url| url.join(&filename))
+                                    .map_err(|err| Error::Config(format!("Invalid download url: {}", err)))?;
+                                download(&url, &filename)?;
+                                if env::consts::OS == "windows" {
+                                    zip_extract::extract(Cursor::new(&filename), &storage_path, true)
+                                        .map_err(|err| Error::Download { url: url.to_string(), message: err.to_string() })?;
+                                } else {
+                                    let tar_gz = File::open(&filename).map_err(|err| Error::io(PathBuf::from(&filename), err))?;
+                                    let tar = flate2::read::GzDecoder::new(tar_gz);
+                                    let mut archive = tar::Archive::new(tar);
+                                    archive.unpack(&storage_path).map_err(|err| Error::io(storage_path.clone(), err))?;
                                 }
-                                None => {
-                                    let arch ;
-                                    if env::consts::OS == "windows" {
-                                        arch = "x86_64-pc-windows-msvc"
-                                    } else if env::consts::OS == "macos" {
-                                        arch = "x86_64-apple-darwin"
-                                    } else {
-                                        arch = "x86_64-unknown-linux-gnu"
-                                    }
-                                    if env::consts::OS == "windows" {
-                                        let filename = format!("libparser_{}-{}.zip", cmd.lang, arch);
-                                        let url = Url::parse(&config.host).unwrap().join(&config.download_path).unwrap().join(&filename).unwrap();
-                                        match reqwest::blocking::get(url) {
-                                            Ok(response) => {
-                                                let mut archive = File::create(&filename).unwrap();
-                                                match response.bytes() {
-                                                    Ok(bytes) => {
-                                                        archive.write(&bytes).unwrap();
-                                                    }
-                                                    Err(err) => clap::Error::raw(clap::error::ErrorKind::Io, err).exit()
-                                                };
-                                                zip_extract::extract(Cursor::new(&filename), &storage_path, true).unwrap();
-                                                remove_file(&filename).unwrap();
-                                            }
-                                            Err(err) => clap::Error::raw(clap::error::ErrorKind::Io, err).exit()
-                                        };
-                                    } else {
-                                        let filename = format!("libparser_{}-{}.tar.gz", cmd.lang, arch);
-                                        let url = Url::parse(&config.host).unwrap().join(&config.download_path).unwrap().join(&filename).unwrap();
-                                        match reqwest::blocking::get(url) {
-                                            Ok(response) => {
-                                                let mut archive = File::create(&filename).unwrap();
-                                                match response.bytes() {
-                                                    Ok(bytes) => {
-                                                        archive.write(&bytes).unwrap();
-                                                    }
-                                                    Err(err) => clap::Error::raw(clap::error::ErrorKind::Io, err).exit()
-                                                };
-                                                let tar_gz = File::open(&filename).unwrap();
-                                                let tar = flate2::read::GzDecoder::new(tar_gz);
-                                                let mut archive = tar::Archive::new(tar);
-                                                archive.unpack(&storage_path)?;
-                                                remove_file(&filename).unwrap();
-                                            }
-                                            Err(err) => clap::Error::raw(clap::error::ErrorKind::Io, err).exit()
-                                        };
-                                    }
-                                },
+                                remove_file(&filename).map_err(|err| Error::io(PathBuf::from(&filename), err))?;
                             }
-
                         }
                     }
                 },
                 ControlCommands::Code(code) => {
-                    if code.diff {
-                        let decompressed = fs::decompress_file(code.output_file);
-
-                        let mut old_commented_code_vec: Vec<CommentedCode> = bincode::deserialize(&decompressed).unwrap();
-                        let mut new_commented_code_vec: Vec<CommentedCode> = code::get_control_commented_code(code.directory, SupportedLanguage::from(code.lang).language(), code.ext);
-                        let matching: Vec<CommentedCode> = get_common_values(&old_commented_code_vec, &new_commented_code_vec);
-                        old_commented_code_vec.retain(|x| !matching.contains(x));
-                        new_commented_code_vec.retain(|x| !matching.contains(x));
-
-                        if old_commented_code_vec.is_empty() && new_commented_code_vec.is_empty() {
-                            println!("No changes detected.");
-                        } else {
-                            let mut output = String::new();
-                            for commented_code in old_commented_code_vec {
-                                output.push_str(&(format!("\t- {:?};{:?}:{:?}\n", commented_code.get_path(), commented_code.get_start().row + 1, commented_code.get_end().row + 1)));
-                            }
-                            for commented_code in new_commented_code_vec {
-                                output.push_str(&format!("\t+ {:?};{:?}:{:?}\n", commented_code.get_path(), commented_code.get_start().row + 1, commented_code.get_end().row + 1));
-                            }
-                            print!("Changes Detected!\n {}", output);
+                    let (languages, extensions) = languages(&code)?;
+                    let language_names: BTreeSet<String> = extensions
+                        .iter()
+                        .filter_map(|extension| languages.get_language(extension))
+                        .map(|language| language.to_string())
+                        .collect();
+                    let language_names: Vec<String> = language_names.into_iter().collect();
+                    let header = control_log::LogHeader::new(language_names.join(","), extensions.clone(), code.capture.to_string());
+                    let filter = fs::FileFilter::new(extensions)
+                        .with_include(code.include)
+                        .with_exclude(code.exclude)
+                        .with_ignore_files(!code.no_ignore);
+                    let scan = code::get_control_commented_code(code.directory, &languages, filter, code.capture)?;
+                    let findings = scan.get_findings();
+                    for finding in &findings {
+                        eprintln!("warning: {}", finding);
+                    }
+                    if code.strict && !findings.is_empty() {
+                        return Err(Error::Skipped(findings));
+                    }
 
-                            exit(1);
-                        }
+                    if code.diff {
+                        let old_commented_code_vec: Vec<CommentedCode> = match fs::read_log(code.output_file) {
+                            Ok(log) => log.into_commented_code(),
+                            Err(Error::MissingLog(_)) if code.allow_missing_baseline => Vec::new(),
+                            Err(Error::MissingLog(path)) => return Err(Error::MissingBaseline(path)),
+                            Err(err) => return Err(err),
+                        };
+                        let new_commented_code_vec: Vec<CommentedCode> = scan.into_commented_code();
+                        return print_diff(old_commented_code_vec, new_commented_code_vec, comparison(code.exact, code.ignore_comments), code.diff_format);
                     } else {
-                        let commented_code = code::get_control_commented_code(code.directory, SupportedLanguage::from(code.lang).language(), code.ext);
+                        let mut commented_code = scan.into_commented_code();
                         if commented_code.is_empty() {
-                            clap::Error::raw(clap::error::ErrorKind::Io, "No commented code found.\n").exit();
-                        } else {
-                            let file = File::create(code.output_file.to_str().unwrap().to_string()).unwrap();
-                            let mut file_writer = brotli::CompressorWriter::new(file, 4096, 5, 22);
-                            match bincode::serialize_into(&mut file_writer, &commented_code) {
-                                Ok(_) => {
-                                    println!("{} generated.", code.output_file.to_str().unwrap().to_string());
-                                }
-                                Err(err) => {
-                                    clap::Error::raw(clap::error::ErrorKind::Io, format!("Error generating file: {}", err)).exit();
-                                }
-                            }
+                            return Err(Error::NoControls);
+                        }
+                        if code.omit_content {
+                            commented_code = commented_code.into_iter().map(CommentedCode::without_content).collect();
                         }
+                        fs::write_log(code.output_file.clone(), &control_log::ControlLog::new(header, commented_code))?;
+                        println!("{} generated.", code.output_file.display());
                     }
                 }
+                ControlCommands::Diff(cmd) => {
+                    let old_commented_code_vec = fs::read_log(cmd.old_log)?.into_commented_code();
+                    let new_commented_code_vec = fs::read_log(cmd.new_log)?.into_commented_code();
+                    return print_diff(old_commented_code_vec, new_commented_code_vec, comparison(cmd.exact, cmd.ignore_comments), cmd.diff_format);
+                }
                 ControlCommands::Log(log) => {
-                    let decompressed = fs::decompress_file(log.log_path);
-                    let commented_code_vec: Vec<CommentedCode> = bincode::deserialize(&decompressed).unwrap();
-                    match serde_json::to_string_pretty(&commented_code_vec) {
-                        Ok(content) => {
-                            println!("{}", content);
-                        }
-                        Err(_) => {
-                            clap::Error::raw(clap::error::ErrorKind::Io, "Error parsing log file.").exit();
-                        }
-                    }
+                    let commented_code_vec: Vec<CommentedCode> = fs::read_log(log.log_path)?.into_commented_code();
+                    print!("{}", report::render(&commented_code_vec, log.format)?);
                 }
             }
         }
     }
-    Ok(())
+    Ok(exit_code::SUCCESS)
 }
@@ -1,144 +1,227 @@
+use crate::error::Error;
+use language_plugin::LanguagePlugin;
 use libloading::{Library, Symbol};
-use std::{env, io};
-use std::process::exit;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use tree_sitter::{Language, Node, Parser, Tree, TreeCursor};
-#[cfg(debug_assertions)]
-use std::path::PathBuf;
-
-trait LanguagePlugin {
-    fn language(&self) -> Language;
-}
 
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum SupportedLanguage {
     Java,
     JavaScript,
     TypeScript,
+    Python,
+    Go,
+    Rust,
+    C,
+    Cpp,
 }
 
-impl From<String> for SupportedLanguage {
-    fn from(language: String) -> SupportedLanguage {
-        match language.as_str() {
-            "java" => SupportedLanguage::Java,
-            "js" => SupportedLanguage::JavaScript,
-            "ts" => SupportedLanguage::TypeScript,
-            _ => {
-                println!("Error: Unsupported language");
-                exit(1);
-            }
+/// The built-in mapping of file extensions to languages, see `LanguageMap`.
+const DEFAULT_EXTENSIONS: [(&str, SupportedLanguage); 16] = [
+    ("java", SupportedLanguage::Java),
+    ("js", SupportedLanguage::JavaScript),
+    ("jsx", SupportedLanguage::JavaScript),
+    ("mjs", SupportedLanguage::JavaScript),
+    ("cjs", SupportedLanguage::JavaScript),
+    ("ts", SupportedLanguage::TypeScript),
+    ("tsx", SupportedLanguage::TypeScript),
+    ("py", SupportedLanguage::Python),
+    ("go", SupportedLanguage::Go),
+    ("rs", SupportedLanguage::Rust),
+    ("c", SupportedLanguage::C),
+    ("h", SupportedLanguage::C),
+    ("cpp", SupportedLanguage::Cpp),
+    ("cc", SupportedLanguage::Cpp),
+    ("cxx", SupportedLanguage::Cpp),
+    ("hpp", SupportedLanguage::Cpp),
+];
+
+impl FromStr for SupportedLanguage {
+    type Err = Error;
+
+    fn from_str(language: &str) -> Result<SupportedLanguage, Error> {
+        match language {
+            "java" => Ok(SupportedLanguage::Java),
+            "js" => Ok(SupportedLanguage::JavaScript),
+            "ts" => Ok(SupportedLanguage::TypeScript),
+            "py" => Ok(SupportedLanguage::Python),
+            "go" => Ok(SupportedLanguage::Go),
+            "rs" => Ok(SupportedLanguage::Rust),
+            "c" => Ok(SupportedLanguage::C),
+            "cpp" => Ok(SupportedLanguage::Cpp),
+            _ => Err(Error::UnsupportedLanguage(language.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for SupportedLanguage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SupportedLanguage::Java => write!(f, "java"),
+            SupportedLanguage::JavaScript => write!(f, "js"),
+            SupportedLanguage::TypeScript => write!(f, "ts"),
+            SupportedLanguage::Python => write!(f, "py"),
+            SupportedLanguage::Go => write!(f, "go"),
+            SupportedLanguage::Rust => write!(f, "rs"),
+            SupportedLanguage::C => write!(f, "c"),
+            SupportedLanguage::Cpp => write!(f, "cpp"),
         }
     }
 }
 
 impl SupportedLanguage {
-    fn load_plugin(name: &str) -> Result<Box<dyn LanguagePlugin>, io::Error> {
-        unsafe {
-            let lib_name = format!("{}.{}", name, env::consts::DLL_EXTENSION);
-            let file_path = {
-                #[cfg(debug_assertions)]
-                {
-                    let file_path = match PathBuf::from(env!("CARGO_MANIFEST_DIR")).parent() {
-                        Some(path) => path.join("target").join("debug").join(lib_name),
-                        None => {
-                            println!("Error: Could not get parent directory of config file");
-                            exit(1);
-                        }
-                    };
-
-                    file_path
-                }
-                #[cfg(not(debug_assertions))]
-                {
-                    let file_path = match confy::get_configuration_file_path("control", "config") {
-                        Ok(path) => match path.parent() {
-                            Some(path) => path.join(format!("{}", lib_name)),
-                            None => {
-                                println!("Error: Could not get parent directory of config file");
-                                exit(1);
-                            }
-                        },
-                        Err(err) => {
-                            println!("Error: {}", err);
-                            exit(1);
-                        }
-                    };
-                    file_path
-                }
-            };
+    fn plugin_path(lib_name: String) -> Result<PathBuf, Error> {
+        #[cfg(debug_assertions)]
+        {
+            match PathBuf::from(env!("CARGO_MANIFEST_DIR")).parent() {
+                Some(path) => Ok(path.join("target").join("debug").join(lib_name)),
+                None => Err(Error::PluginNotFound {
+                    path: PathBuf::from(lib_name),
+                    message: "Could not get parent directory of the workspace".to_string(),
+                }),
+            }
+        }
+        #[cfg(not(debug_assertions))]
+        {
+            match confy::get_configuration_file_path("control", "config") {
+                Ok(path) => match path.parent() {
+                    Some(path) => Ok(path.join(lib_name)),
+                    None => Err(Error::PluginNotFound {
+                        path: PathBuf::from(lib_name),
+                        message: "Could not get parent directory of config file".to_string(),
+                    }),
+                },
+                Err(err) => Err(Error::PluginNotFound {
+                    path: PathBuf::from(lib_name),
+                    message: err.to_string(),
+                }),
+            }
+        }
+    }
 
+    fn open_plugin(name: &str) -> Result<(PathBuf, Library), Error> {
+        let file_path =
+            SupportedLanguage::plugin_path(format!("{}.{}", name, env::consts::DLL_EXTENSION))?;
+        let not_found = |err: libloading::Error| Error::PluginNotFound {
+            path: file_path.clone(),
+            message: err.to_string(),
+        };
+        unsafe {
             #[cfg(target_os = "linux")]
-                let lib: Library = {
-                match ::libloading::os::unix::Library::open(Some(file_path), 0x2 | 0x1000) {
-                    Ok(lib) => lib,
-                    _ => {
-                        println!("Error loading plugin in linux");
-                        exit(1);
-                    }
-                }.into()
-            };
+            let lib: Library =
+                ::libloading::os::unix::Library::open(Some(file_path.clone()), 0x2 | 0x1000)
+                    .map_err(not_found)?
+                    .into();
             #[cfg(not(target_os = "linux"))]
-                let lib = match Library::new(file_path) {
-                Ok(lib) => lib,
-                Err(e) => {
-                    println!("Error loading plugin: {}", e);
-                    exit(1);
-                }
-            };
-            let func: Symbol<fn() -> Box<dyn LanguagePlugin>> = match lib.get(b"plugin_init\0") {
-                Ok(f) => f,
-                Err(e) => {
-                    println!("Error initializing plugin: {}", e);
-                    exit(1);
-                }
-            };
+            let lib = Library::new(file_path.clone()).map_err(not_found)?;
+            Ok((file_path, lib))
+        }
+    }
+
+    fn init_plugin(file_path: &Path, lib: &Library) -> Result<Box<dyn LanguagePlugin>, Error> {
+        unsafe {
+            let func: Symbol<fn() -> Box<dyn LanguagePlugin>> =
+                lib.get(b"plugin_init\0").map_err(|err| Error::SymbolMissing {
+                    path: file_path.to_path_buf(),
+                    symbol: "plugin_init".to_string(),
+                    message: err.to_string(),
+                })?;
             Ok(func())
         }
     }
 
-    #[allow(dead_code)]
-    pub fn language(&self) -> Language {
+    fn plugin_name(&self) -> &'static str {
         match self {
-            SupportedLanguage::Java => match SupportedLanguage::load_plugin("libparser_java") {
-                Ok(plugin) => {
-                    plugin.language()
-                }
-                Err(_) => {
-                    println!("Error: Could not load plugin at language: Java");
-                    exit(1);
-                }
-            }
-            SupportedLanguage::JavaScript => match SupportedLanguage::load_plugin("libparser_js") {
-                Ok(plugin) => {
-                    plugin.language()
-                }
-                Err(_) => {
-                    println!("Error: Could not load plugin at language: JavaScript");
-                    exit(1);
-                }
-            }
-            SupportedLanguage::TypeScript => match SupportedLanguage::load_plugin("libparser_ts") {
-                Ok(plugin) => {
-                    plugin.language()
-                }
-                Err(_) => {
-                    println!("Error: Could not load plugin at language: TypeScript");
-                    exit(1);
-                }
+            SupportedLanguage::Java => "libparser_java",
+            SupportedLanguage::JavaScript => "libparser_js",
+            SupportedLanguage::TypeScript => "libparser_ts",
+            SupportedLanguage::Python => "libparser_py",
+            SupportedLanguage::Go => "libparser_go",
+            SupportedLanguage::Rust => "libparser_rs",
+            SupportedLanguage::C => "libparser_c",
+            SupportedLanguage::Cpp => "libparser_cpp",
+        }
+    }
+
+    pub fn language(&self) -> Result<Language, Error> {
+        let (file_path, lib) = SupportedLanguage::open_plugin(self.plugin_name())?;
+        Ok(SupportedLanguage::init_plugin(&file_path, &lib)?.language())
+    }
+
+    /// The grammar for files with the given extension, e.g. TSX only for `.tsx` files.
+    ///
+    /// Plugins with more than one grammar export `plugin_language_for` next to `plugin_init`, so
+    /// the `LanguagePlugin` vtable stays the same for plugins downloaded before it existed.
+    pub fn language_for(&self, extension: &str) -> Result<Language, Error> {
+        let (file_path, lib) = SupportedLanguage::open_plugin(self.plugin_name())?;
+        unsafe {
+            match lib.get::<extern "C" fn(&str) -> Language>(b"plugin_language_for\0") {
+                Ok(func) => Ok(func(extension)),
+                Err(_) => Ok(SupportedLanguage::init_plugin(&file_path, &lib)?.language()),
             }
         }
     }
 }
 
-#[allow(dead_code)]
-pub fn parse(code: &str, language: Language) -> Tree {
-    let mut parser = Parser::new();
-    match parser.set_language(language) {
-        Ok(_) => {}
-        Err(err) => {
-            println!("Error: {}", err);
+/// Selects the language that parses a file by its extension.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LanguageMap {
+    languages: BTreeMap<String, SupportedLanguage>,
+}
+
+impl Default for LanguageMap {
+    /// Maps `.java` to Java, `.js`, `.jsx`, `.mjs` and `.cjs` to JavaScript, `.ts` and `.tsx` to
+    /// TypeScript, `.py` to Python, `.go` to Go, `.rs` to Rust, `.c` and `.h` to C and `.cpp`, `.cc`,
+    /// `.cxx` and `.hpp` to C++.
+    fn default() -> LanguageMap {
+        LanguageMap {
+            languages: DEFAULT_EXTENSIONS
+                .iter()
+                .map(|(extension, language)| (extension.to_string(), *language))
+                .collect(),
         }
     }
+}
+
+impl LanguageMap {
+    /// Parses files with the extension `extension` as `language`, replacing any previous mapping.
+    pub fn with_language(mut self, extension: String, language: SupportedLanguage) -> LanguageMap {
+        self.languages.insert(extension, language);
+        self
+    }
 
-    return parser.parse(code, None).unwrap();
+    pub fn get_language(&self, extension: &str) -> Option<SupportedLanguage> {
+        self.languages.get(extension).copied()
+    }
+
+    /// All mapped extensions, sorted.
+    pub fn get_extensions(&self) -> Vec<String> {
+        self.languages.keys().cloned().collect()
+    }
+
+    /// The extensions mapped to `language`, sorted.
+    pub fn get_extensions_of(&self, language: SupportedLanguage) -> Vec<String> {
+        self.languages
+            .iter()
+            .filter(|(_, mapped)| **mapped == language)
+            .map(|(extension, _)| extension.clone())
+            .collect()
+    }
+}
+
+pub fn parse(code: &str, language: Language) -> Result<Tree, Error> {
+    let mut parser = Parser::new();
+    parser
+        .set_language(language)
+        .map_err(|err| Error::Parse(err.to_string()))?;
+
+    parser
+        .parse(code, None)
+        .ok_or_else(|| Error::Parse("Parsing was cancelled".to_string()))
 }
 
 pub fn traverse_and_select(node: Node, select: fn(TreeCursor) -> Option<Node>) -> Vec<Node> {
@@ -146,10 +229,8 @@ pub fn traverse_and_select(node: Node, select: fn(TreeCursor) -> Option<Node>) -
     let mut cursor = node.walk();
     cursor.goto_first_child();
     loop {
-        let selected_node = select(cursor.clone());
-        match selected_node {
-            Some(node) => nodes.push(node),
-            None => (),
+        if let Some(node) = select(cursor.clone()) {
+            nodes.push(node);
         }
         if cursor.goto_first_child() {
             continue;
@@ -171,7 +252,7 @@ public class Test {
         System.out.println("Hello, World!");
     }
 }"#;
-    let tree = parse(code, SupportedLanguage::Java.language());
+    let tree = parse(code, SupportedLanguage::Java.language().unwrap()).unwrap();
     let nodes = traverse_and_select(tree.root_node(), |mut cursor: TreeCursor| -> Option<Node> {
         if cursor.node().kind() == "comment"
             || cursor.node().kind() == "block_comment"
@@ -181,7 +262,7 @@ public class Test {
             return Option::from(cursor.node());
         }
 
-        return None;
+        None
     });
     assert_eq!(nodes.len(), 2);
 
@@ -208,7 +289,7 @@ fn check_parse_javascript_comment() {
   // control T84
   return a + b;
 }";
-    let tree = parse(code, SupportedLanguage::JavaScript.language());
+    let tree = parse(code, SupportedLanguage::JavaScript.language().unwrap()).unwrap();
     let root_node = tree.root_node();
     let mut cursor = root_node.walk();
 
@@ -237,7 +318,7 @@ fn check_parse_javascript_multiline_comment() {
   **/
   return a + b;
 }";
-    let tree = parse(code, SupportedLanguage::JavaScript.language());
+    let tree = parse(code, SupportedLanguage::JavaScript.language().unwrap()).unwrap();
     let root_node = tree.root_node();
     let mut cursor = root_node.walk();
 
@@ -260,7 +341,7 @@ fn check_parse_javascript() {
     let code = "function add(a, b) {
   return a + b;
 }";
-    let tree = parse(code, SupportedLanguage::JavaScript.language());
+    let tree = parse(code, SupportedLanguage::JavaScript.language().unwrap()).unwrap();
     let root_node = tree.root_node();
 
     assert_eq!(root_node.kind(), "program");
@@ -308,7 +389,7 @@ fn check_parse_java_comment() {
         return a + b;
     }
 }";
-    let tree = parse(code, SupportedLanguage::Java.language());
+    let tree = parse(code, SupportedLanguage::Java.language().unwrap()).unwrap();
     let root_node = tree.root_node();
     let mut cursor = root_node.walk();
 
@@ -343,7 +424,7 @@ fn check_parse_java_multiline_comment() {
         return a + b;
     }
 }";
-    let tree = parse(code, SupportedLanguage::Java.language());
+    let tree = parse(code, SupportedLanguage::Java.language().unwrap()).unwrap();
     let root_node = tree.root_node();
     let mut cursor = root_node.walk();
 
@@ -375,7 +456,7 @@ fn check_parse_java() {
         return a + b;
     }
 }";
-    let tree = parse(code, SupportedLanguage::Java.language());
+    let tree = parse(code, SupportedLanguage::Java.language().unwrap()).unwrap();
     let root_node = tree.root_node();
 
     assert_eq!(root_node.kind(), "program");
@@ -439,3 +520,38 @@ fn check_parse_java() {
     cursor.goto_next_sibling();
     assert_eq!(cursor.node().kind(), "}");
 }
+
+#[test]
+fn check_unsupported_language() {
+    match "guava".parse::<SupportedLanguage>() {
+        Err(Error::UnsupportedLanguage(language)) => assert_eq!(language, "guava"),
+        _ => panic!("expected an unsupported language error"),
+    }
+}
+
+#[test]
+fn check_language_map() {
+    let languages = LanguageMap::default();
+    assert_eq!(languages.get_language("jsx"), Some(SupportedLanguage::JavaScript));
+    assert_eq!(languages.get_language("java"), Some(SupportedLanguage::Java));
+    assert_eq!(languages.get_language("md"), None);
+    assert_eq!(languages.get_extensions_of(SupportedLanguage::TypeScript), vec!["ts", "tsx"]);
+
+    let languages = languages.with_language("js".to_string(), SupportedLanguage::TypeScript);
+    assert_eq!(languages.get_language("js"), Some(SupportedLanguage::TypeScript));
+}
+
+#[test]
+fn check_parse_typescript_by_extension() {
+    let code = std::fs::read_to_string(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/resources/ts/assertions.ts"),
+    )
+    .unwrap();
+    let typescript = SupportedLanguage::TypeScript.language_for("ts").unwrap();
+    let tsx = SupportedLanguage::TypeScript.language_for("tsx").unwrap();
+    assert!(!parse(&code, typescript).unwrap().root_node().has_error());
+    assert!(parse(&code, tsx).unwrap().root_node().has_error());
+
+    let component = "const Component = () => <div>Component</div>;";
+    assert!(!parse(component, tsx).unwrap().root_node().has_error());
+}
@@ -18,7 +18,7 @@ fn file_doesnt_exist() -> Result<(), Box<dyn std::error::Error>> {
         "./turd/mcdermott",
     ]);
     cmd.assert()
-        .failure()
+        .code(7)
         .stderr(predicate::str::contains("error: No commented code found."));
 
     Ok(())
@@ -58,8 +58,249 @@ fn fail_unsupported_language() -> Result<(), Box<dyn std::error::Error>> {
         "hashDerp",
         "./tests/resources/java",
     ]);
-    cmd.assert().failure();
-    // .stderr(predicate::str::contains("Language not supported"));
+    cmd.assert()
+        .code(3)
+        .stderr(predicate::str::contains("Unsupported language: guava"));
+
+    Ok(())
+}
+
+#[test]
+fn strict_fails_on_orphaned_control() -> Result<(), Box<dyn std::error::Error>> {
+    use assert_fs::prelude::*;
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("Orphan.java").write_str(
+        "class Orphan {\n    // control AC-2\n    void run() {\n        // control AC-1\n    }\n}\n",
+    )?;
+    let output_file = dir.child(".control-log");
+
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(["control", "code", "--lang", "java", "--ext", "java", "--output-file"])
+        .arg(output_file.path())
+        .arg(dir.path());
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("Orphan.java:4: control AC-1 has nothing to attach to"));
+
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(["control", "code", "--strict", "--lang", "java", "--ext", "java", "--output-file"])
+        .arg(output_file.path())
+        .arg(dir.path());
+    cmd.assert()
+        .code(5)
+        .stderr(predicate::str::contains("1 control comment(s) were skipped."));
+
+    Ok(())
+}
+
+#[test]
+fn diff_against_version_0_log() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args([
+        "control",
+        "code",
+        "--diff",
+        "--lang",
+        "java",
+        "--ext",
+        "java",
+        "--output-file",
+        "tests/fixtures/.control-log.java.br",
+        "./tests/resources/java",
+    ]);
+    // The version 0 log holds the same control, logged with a path relative to the repository.
+    cmd.assert()
+        .code(1)
+        .stdout(predicate::str::contains(
+            "\t> \"cli/tests/resources/java/Program.java\";6:6 -> \"Program.java\";6:6",
+        ))
+        .stdout(predicate::str::contains("\t+ ").not())
+        .stdout(predicate::str::contains("\t- ").not())
+        .stdout(predicate::str::contains("\t~ ").not());
+
+    Ok(())
+}
+
+#[test]
+fn diff_without_baseline() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let output_file = dir.path().join(".control-log");
+    let args = ["control", "code", "--diff", "--lang", "java", "--ext", "java"];
+
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(args)
+        .arg("--output-file")
+        .arg(&output_file)
+        .arg("./tests/resources/java");
+    cmd.assert()
+        .code(6)
+        .stderr(predicate::str::contains("error: No baseline found at"));
+
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(args)
+        .arg("--allow-missing-baseline")
+        .arg("--output-file")
+        .arg(&output_file)
+        .arg("./tests/resources/java");
+    cmd.assert()
+        .code(1)
+        .stdout(predicate::str::contains("\t+ \"Program.java\";6:6"));
+
+    Ok(())
+}
+
+#[test]
+fn diff_ignores_formatting() -> Result<(), Box<dyn std::error::Error>> {
+    use assert_fs::prelude::*;
+    let dir = assert_fs::TempDir::new()?;
+    let source = dir.child("src/Program.java");
+    let output_file = dir.path().join(".control-log");
+    let args = ["control", "code", "--lang", "java", "--ext", "java"];
+
+    source.write_str("class Program {\n    /* control HE-110 */\n    void hello() { System.out.println(\"Hello\"); }\n}\n")?;
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(args).arg("--output-file").arg(&output_file).arg(dir.child("src").path());
+    cmd.assert().success();
+
+    source.write_str("class Program {\n\n  /* control HE-110 */\n  void hello() {\n    System.out.println( \"Hello\" );\n  }\n}\n")?;
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(args).arg("--diff").arg("--output-file").arg(&output_file).arg(dir.child("src").path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("No changes detected."));
+
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(args).arg("--diff").arg("--exact").arg("--output-file").arg(&output_file).arg(dir.child("src").path());
+    cmd.assert().code(1);
+
+    Ok(())
+}
+
+#[test]
+fn diff_exact_reports_moves() -> Result<(), Box<dyn std::error::Error>> {
+    use assert_fs::prelude::*;
+    let dir = assert_fs::TempDir::new()?;
+    let source = dir.child("src/Program.java");
+    let output_file = dir.path().join(".control-log");
+    let args = ["control", "code", "--lang", "java", "--ext", "java"];
+
+    source.write_str("class Program {\n    /* control HE-110 */\n    void hello() {}\n}\n")?;
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(args).arg("--output-file").arg(&output_file).arg(dir.child("src").path());
+    cmd.assert().success();
+
+    source.write_str("class Program {\n    int a;\n    int b;\n    /* control HE-110 */\n    void hello() {}\n}\n")?;
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(args).arg("--diff").arg("--output-file").arg(&output_file).arg(dir.child("src").path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("No changes detected."));
+
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(args).arg("--diff").arg("--exact").arg("--output-file").arg(&output_file).arg(dir.child("src").path());
+    cmd.assert()
+        .code(1)
+        .stdout(predicate::str::contains("\t> \"Program.java\";3:3 -> \"Program.java\";5:5"));
+
+    Ok(())
+}
+
+#[test]
+fn diff_as_json() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(["control", "code", "--diff", "--allow-missing-baseline", "--diff-format", "json"])
+        .args(["--lang", "java", "--ext", "java", "--output-file"])
+        .arg(dir.path().join(".control-log"))
+        .arg("./tests/resources/java");
+    cmd.assert()
+        .code(1)
+        .stdout(predicate::str::contains("\"kind\": \"added\""))
+        .stdout(predicate::str::contains("\"old\": null"));
+
+    Ok(())
+}
+
+#[test]
+fn diff_two_logs() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let new_log = dir.path().join(".control-log");
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(["control", "code", "--lang", "java", "--ext", "java", "--output-file"])
+        .arg(&new_log)
+        .arg("./tests/resources/java");
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(["control", "diff"]).arg(&new_log).arg(&new_log);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("No changes detected."));
+
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(["control", "diff", "tests/fixtures/.control-log.java.br"]).arg(&new_log);
+    cmd.assert()
+        .code(1)
+        .stdout(predicate::str::contains("Changes Detected!"));
+
+    Ok(())
+}
+
+#[test]
+fn log_is_independent_of_scan_path() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let relative_log = dir.path().join("relative.control-log");
+    let absolute_log = dir.path().join("absolute.control-log");
+    let args = ["control", "code", "--lang", "js", "--ext", "js", "--ext", "jsx", "--output-file"];
+
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(args).arg(&relative_log).arg("./tests/resources/js");
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(args)
+        .arg(&absolute_log)
+        .arg(std::env::current_dir()?.join("tests/resources/js"));
+    cmd.assert().success();
+
+    assert_eq!(std::fs::read(&relative_log)?, std::fs::read(&absolute_log)?);
+
+    Ok(())
+}
+
+#[test]
+fn scan_multiple_languages() -> Result<(), Box<dyn std::error::Error>> {
+    use assert_fs::prelude::*;
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("src/Main.java")
+        .write_str("class Main {\n    // control AC-1\n    void run() {}\n}\n")?;
+    dir.child("src/app.jsx")
+        .write_str("// control AC-2\nconst App = () => <div/>;\n")?;
+    dir.child("src/util.cjs")
+        .write_str("// control AC-3\nmodule.exports = {};\n")?;
+    let output_file = dir.path().join(".control-log");
+
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(["control", "code", "--map", "cjs=ts", "--output-file"])
+        .arg(&output_file)
+        .arg(dir.child("src").path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(["control", "log", "--format", "table", "--log-path"]).arg(&output_file);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Main.java"))
+        .stdout(predicate::str::contains("app.jsx"))
+        .stdout(predicate::str::contains("util.cjs"));
+
+    let mut cmd = Command::cargo_bin("control")?;
+    cmd.args(["control", "code", "--ext", "kt", "--output-file"])
+        .arg(&output_file)
+        .arg(dir.child("src").path());
+    cmd.assert()
+        .code(3)
+        .stderr(predicate::str::contains("No language for extension kt"));
 
     Ok(())
 }
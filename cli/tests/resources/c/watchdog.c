@@ -0,0 +1,27 @@
+#include <stdio.h>
+
+/* control SI-17 */
+#ifdef WATCHDOG_ENABLED
+static void reset_watchdog(void) {
+    fputs("watchdog reset\n", stderr);
+}
+#endif
+
+#ifdef SECURE_BOOT
+// control SI-7
+// Firmware images are verified before they are booted.
+int verify_image(const unsigned char *image, unsigned long length) {
+    if (length == 0) {
+        return -1;
+    }
+    // control AU-9
+    log_verification(image, length);
+    return check_signature(image, length);
+}
+#endif
+
+//! control SC-28
+//! Keys are wiped from memory as soon as they are no longer needed.
+void wipe_key(unsigned char *key, unsigned long length) {
+    memset(key, 0, length);
+}
@@ -0,0 +1,22 @@
+#include <string>
+
+namespace sensor {
+
+// control SC-13
+class Cipher {
+public:
+    std::string encrypt(const std::string &plain) const;
+};
+
+#if defined(TELEMETRY)
+/// control AU-12
+void report(const std::string &event) {
+    auto send = [&event]() {
+        // control SC-8
+        transmit(event);
+    };
+    send();
+}
+#endif
+
+}
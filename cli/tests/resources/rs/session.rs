@@ -0,0 +1,25 @@
+//! control SC-23
+//! Sessions are bound to a single client and expire after inactivity.
+
+use std::time::{Duration, Instant};
+
+/// A session of an authenticated user.
+/// control AC-12 owner=iam
+#[derive(Clone, Debug)]
+pub struct Session {
+    user: String,
+    last_seen: Instant,
+}
+
+impl Session {
+    pub fn is_expired(&self, timeout: Duration) -> bool {
+        // control AC-12(1)
+        self.last_seen.elapsed() > timeout
+    }
+}
+
+mod revocation {
+    //! control AC-2(4)
+
+    pub fn revoke(_user: &str) {}
+}
@@ -0,0 +1,9 @@
+declare const input: unknown;
+
+// control TS-1
+const length = (<string>input).length;
+
+/* control TS-2 */
+const identity = <T>(value: T): T => value;
+
+export { length, identity };
@@ -0,0 +1,27 @@
+extern crate tree_sitter_c;
+extern crate language_plugin;
+extern crate tree_sitter;
+
+use language_plugin::LanguagePlugin;
+use tree_sitter::Language;
+
+struct TreeSitterCPlugin {
+}
+
+impl TreeSitterCPlugin {
+    fn new() -> TreeSitterCPlugin {
+        TreeSitterCPlugin { }
+    }
+}
+
+impl LanguagePlugin for TreeSitterCPlugin {
+    fn language(&self) -> Language {
+        tree_sitter_c::language()
+    }
+}
+
+#[no_mangle]
+#[allow(improper_ctypes_definitions)]
+pub extern "C" fn plugin_init() -> Box<dyn LanguagePlugin> {
+    Box::new(TreeSitterCPlugin::new())
+}
@@ -0,0 +1,27 @@
+extern crate tree_sitter_cpp;
+extern crate language_plugin;
+extern crate tree_sitter;
+
+use language_plugin::LanguagePlugin;
+use tree_sitter::Language;
+
+struct TreeSitterCppPlugin {
+}
+
+impl TreeSitterCppPlugin {
+    fn new() -> TreeSitterCppPlugin {
+        TreeSitterCppPlugin { }
+    }
+}
+
+impl LanguagePlugin for TreeSitterCppPlugin {
+    fn language(&self) -> Language {
+        tree_sitter_cpp::language()
+    }
+}
+
+#[no_mangle]
+#[allow(improper_ctypes_definitions)]
+pub extern "C" fn plugin_init() -> Box<dyn LanguagePlugin> {
+    Box::new(TreeSitterCppPlugin::new())
+}
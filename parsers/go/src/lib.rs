@@ -0,0 +1,27 @@
+extern crate tree_sitter_go;
+extern crate language_plugin;
+extern crate tree_sitter;
+
+use language_plugin::LanguagePlugin;
+use tree_sitter::Language;
+
+struct TreeSitterGoPlugin {
+}
+
+impl TreeSitterGoPlugin {
+    fn new() -> TreeSitterGoPlugin {
+        TreeSitterGoPlugin { }
+    }
+}
+
+impl LanguagePlugin for TreeSitterGoPlugin {
+    fn language(&self) -> Language {
+        tree_sitter_go::language()
+    }
+}
+
+#[no_mangle]
+#[allow(improper_ctypes_definitions)]
+pub extern "C" fn plugin_init() -> Box<dyn LanguagePlugin> {
+    Box::new(TreeSitterGoPlugin::new())
+}
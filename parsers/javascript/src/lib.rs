@@ -1,12 +1,10 @@
 extern crate tree_sitter_javascript;
+extern crate language_plugin;
 extern crate tree_sitter;
 
+use language_plugin::LanguagePlugin;
 use tree_sitter::Language;
 
-pub trait LanguagePlugin {
-    fn language(&self) -> Language;
-}
-
 struct TreeSitterJavaScriptPlugin {
 }
 
@@ -0,0 +1,14 @@
+extern crate tree_sitter;
+
+use tree_sitter::Language;
+
+/// The interface between `control` and its parser plugins, each a dylib exporting
+/// `plugin_init() -> Box<dyn LanguagePlugin>`.
+///
+/// Plugins are called through the vtable of this trait, so `control` and every plugin must be
+/// built against the same version of it: adding, removing or reordering a method breaks plugins
+/// built before the change. New entry points are exported as optional symbols instead, such as
+/// `plugin_language_for(extension: &str) -> Language` for plugins with more than one grammar.
+pub trait LanguagePlugin {
+    fn language(&self) -> Language;
+}
@@ -0,0 +1,27 @@
+extern crate tree_sitter_python;
+extern crate language_plugin;
+extern crate tree_sitter;
+
+use language_plugin::LanguagePlugin;
+use tree_sitter::Language;
+
+struct TreeSitterPythonPlugin {
+}
+
+impl TreeSitterPythonPlugin {
+    fn new() -> TreeSitterPythonPlugin {
+        TreeSitterPythonPlugin { }
+    }
+}
+
+impl LanguagePlugin for TreeSitterPythonPlugin {
+    fn language(&self) -> Language {
+        tree_sitter_python::language()
+    }
+}
+
+#[no_mangle]
+#[allow(improper_ctypes_definitions)]
+pub extern "C" fn plugin_init() -> Box<dyn LanguagePlugin> {
+    Box::new(TreeSitterPythonPlugin::new())
+}
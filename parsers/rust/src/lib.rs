@@ -0,0 +1,27 @@
+extern crate tree_sitter_rust;
+extern crate language_plugin;
+extern crate tree_sitter;
+
+use language_plugin::LanguagePlugin;
+use tree_sitter::Language;
+
+struct TreeSitterRustPlugin {
+}
+
+impl TreeSitterRustPlugin {
+    fn new() -> TreeSitterRustPlugin {
+        TreeSitterRustPlugin { }
+    }
+}
+
+impl LanguagePlugin for TreeSitterRustPlugin {
+    fn language(&self) -> Language {
+        tree_sitter_rust::language()
+    }
+}
+
+#[no_mangle]
+#[allow(improper_ctypes_definitions)]
+pub extern "C" fn plugin_init() -> Box<dyn LanguagePlugin> {
+    Box::new(TreeSitterRustPlugin::new())
+}
@@ -1,12 +1,10 @@
 extern crate tree_sitter_typescript;
+extern crate language_plugin;
 extern crate tree_sitter;
 
+use language_plugin::LanguagePlugin;
 use tree_sitter::Language;
 
-pub trait LanguagePlugin {
-    fn language(&self) -> Language;
-}
-
 struct TreeSitterTypeScriptPlugin {
 }
 
@@ -17,7 +15,9 @@ impl TreeSitterTypeScriptPlugin {
 }
 
 impl LanguagePlugin for TreeSitterTypeScriptPlugin {
-    fn language(&self) -> Language { tree_sitter_typescript::language_tsx() }
+    fn language(&self) -> Language {
+        tree_sitter_typescript::language_typescript()
+    }
 }
 
 #[no_mangle]
@@ -25,3 +25,14 @@ impl LanguagePlugin for TreeSitterTypeScriptPlugin {
 pub extern "C" fn plugin_init() -> Box<dyn LanguagePlugin> {
     Box::new(TreeSitterTypeScriptPlugin::new())
 }
+
+/// TSX only for `.tsx` files: in the TSX grammar `<T>value` type assertions and generic arrow
+/// functions parse as JSX.
+#[no_mangle]
+#[allow(improper_ctypes_definitions)]
+pub extern "C" fn plugin_language_for(extension: &str) -> Language {
+    match extension {
+        "tsx" => tree_sitter_typescript::language_tsx(),
+        _ => tree_sitter_typescript::language_typescript(),
+    }
+}